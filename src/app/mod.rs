@@ -1,14 +1,35 @@
+pub mod supervisor;
 mod ui;
 
 use crate::{
+    attachment::{self, Attachment, BlobStore},
+    auth::IssuerKey,
     connector::{
-        create_blocking_redis_connection, input_connector, output_connector, ConnectorEvent,
+        create_blocking_redis_connection, input_connector, output_connector, ping_connector,
+        try_create_async_redis_connection, Author, ConnectorEvent,
     },
     controller_signals::ControllerSignal,
+    e2e::{key_exchange, KeyPair},
+    handoff,
+    lifecycle::{SessionState, StateError},
+    locale::{self, tr, Locale, Text},
+    message::{Kind, Message},
+    metrics,
+    middleware::Pipeline,
+    notes,
+    presence::{self, participants_connector, presence_connector},
+    receipts::receipts_connector,
+    role::Role,
+    session::Session,
+    signing::{Keyring, SigningKey, Trust, SIGNING_KEY_VAR},
+    system::SystemEvent,
+    transcript::{self, ExportOptions, Format},
+    typing::{typing_connector, TypingDebouncer},
     utils,
 };
+use std::{collections::HashMap, path::PathBuf};
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
 pub struct App {
     ui: ui::Ui,
@@ -16,6 +37,24 @@ pub struct App {
     rx: mpsc::Receiver<ControllerSignal>,
     tx: mpsc::Sender<ControllerSignal>,
     output_tx: Option<mpsc::Sender<ConnectorEvent>>,
+    identity: Option<Identity>,
+    roles: HashMap<String, Role>,
+    signing_key: Option<SigningKey>,
+    keyring: Keyring,
+    encrypted: bool,
+    closed: bool,
+    announcement: Option<SystemEvent>,
+    session_id: String,
+    pipeline: Pipeline,
+    typing: TypingDebouncer,
+    metrics_file: Option<PathBuf>,
+    read_only: bool,
+}
+
+struct Identity {
+    username: String,
+    chat_id: String,
+    role: Role,
 }
 
 impl App {
@@ -28,15 +67,52 @@ impl App {
             rx,
             tx,
             output_tx: None,
+            identity: None,
+            roles: HashMap::new(),
+            signing_key: None,
+            keyring: Keyring::default(),
+            encrypted: false,
+            closed: false,
+            announcement: None,
+            session_id: String::new(),
+            pipeline: Pipeline::default(),
+            typing: TypingDebouncer::default(),
+            metrics_file: None,
+            read_only: false,
         }
     }
 
-    pub fn go(mut self, session_id: &str) -> Option<()> {
-        self.ui.init_view();
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    pub fn with_metrics_file(mut self, path: PathBuf) -> Self {
+        self.metrics_file = Some(path);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn go(mut self, session_id: &str, role: Role, token: Option<String>) -> Option<()> {
+        if let Some(path) = self.metrics_file.clone() {
+            self.async_runtime
+                .handle()
+                .spawn(metrics::dump_periodically(path, metrics::DUMP_INTERVAL));
+        }
+        self.session_id = session_id.to_owned();
         let mut con = create_blocking_redis_connection().ok()?;
-        self.init_session(&mut con, session_id)?;
+        Self::init_locale(&mut con, session_id);
+        self.ui.init_view(self.read_only);
+        self.init_session(&mut con, session_id, role, token)?;
+        let read_only = self.read_only;
         self.run();
-        utils::blocking_update_session_timestamp(&mut con, session_id);
+        if !read_only {
+            utils::blocking_update_session_timestamp(&mut con, session_id);
+        }
         Some(())
     }
 }
@@ -46,10 +122,29 @@ impl App {
         loop {
             self.process_signals();
             self.ui.step_next();
+            if let Some(stream_id) = self.ui.read_position() {
+                self.send_to_output(ConnectorEvent::MarkRead { stream_id });
+            }
             if self.ui.stopped() {
                 break;
             }
         }
+        if self.identity.is_some() {
+            metrics::gauge(&metrics::ACTIVE_SESSIONS, -1);
+        }
+        if let Some(identity) = self.identity.as_ref().filter(|_| !self.read_only) {
+            self.async_runtime.block_on(async {
+                if let Ok(mut con) = try_create_async_redis_connection().await {
+                    presence::leave(
+                        &mut con,
+                        &identity.chat_id,
+                        &identity.username,
+                        identity.role,
+                    )
+                    .await;
+                }
+            });
+        }
         // We have to move self into shutdown_timeout(...)
         // That is why it is difficult to impl Drop for App
         self.async_runtime
@@ -57,63 +152,522 @@ impl App {
     }
 
     fn process_signals(&mut self) {
+        let started = std::time::Instant::now();
+        let mut processed = 0;
         while let Ok(signal) = self.rx.try_recv() {
+            processed += 1;
             match signal {
-                ControllerSignal::IncomingMessage { from, message } => {
-                    self.ui.append(&from, &message)
+                ControllerSignal::IncomingMessage { message } => {
+                    if message.kind == Kind::Note
+                        && !(self.may_read_notes() && self.may_write_notes(&message.from))
+                    {
+                        continue;
+                    }
+                    self.on_system_event(&message);
+                    self.ui.append(message)
+                }
+                ControllerSignal::MessageEdited { target, message } => {
+                    self.ui.apply_edit(&target, message)
+                }
+                ControllerSignal::MessageDeleted { target, from } => {
+                    self.ui.apply_delete(&target, &from)
                 }
                 ControllerSignal::Info { message } => self.ui.present_info(&message),
-                ControllerSignal::ConnectTo { username, chat_id } => {
+                ControllerSignal::Rejected { rejection, draft } => self.ui.reject(rejection, draft),
+                ControllerSignal::KeyTimeout { draft } => {
+                    self.ui.refuse(tr(Text::KeyTimeout), draft)
+                }
+                ControllerSignal::ConnectTo {
+                    username,
+                    chat_id,
+                    role,
+                } => {
                     if self.output_tx.is_none() {
                         self.connect_to(
                             username.as_deref().unwrap_or("NONAME"),
                             chat_id.as_deref().unwrap_or("42"),
+                            role,
                         );
                     } else {
                         let _ = self.tx.blocking_send(ControllerSignal::Info {
-                            message: "RUNTIME ERROR:\ntrying to connect when already connected."
-                                .to_owned(),
+                            message: tr(Text::AlreadyConnected).to_owned(),
                         });
                     }
                 }
-                ControllerSignal::OutgoingMessage { message } => {
-                    if let Some(output_tx) = self.output_tx.as_ref() {
-                        let _ = output_tx.blocking_send(ConnectorEvent::Post { message });
+                ControllerSignal::ConnectionState { state } => self.ui.set_connection_state(state),
+                ControllerSignal::Latency { latency } => self.ui.set_latency(latency),
+                ControllerSignal::Participants { participants } => {
+                    self.ui.set_participants(&participants, &self.roles)
+                }
+                ControllerSignal::Typing { state } => self.ui.set_typing(state),
+                ControllerSignal::ReadCursors { cursors } => self.ui.set_read_cursors(cursors),
+                ControllerSignal::Composing => {
+                    if self.typing.input() {
+                        self.send_to_output(ConnectorEvent::Typing { typing: true });
+                    }
+                }
+                ControllerSignal::OutgoingMessage { message, reply_to } => {
+                    self.stop_typing();
+                    if let Some(message) = self.filter_outbound(message) {
+                        self.send_to_output(ConnectorEvent::Post {
+                            message,
+                            reply_to,
+                            attachment: None,
+                        })
+                    }
+                }
+                ControllerSignal::QuickReply { value } => {
+                    self.ui.clear_options();
+                    self.stop_typing();
+                    if self.closed {
+                        self.ui.present_info(tr(Text::ConversationClosed));
+                    } else {
+                        self.send_to_output(ConnectorEvent::Post {
+                            message: value,
+                            reply_to: None,
+                            attachment: None,
+                        })
+                    }
+                }
+                ControllerSignal::OutgoingEdit { target, message } => {
+                    if let Some(message) = self.filter_outbound(message) {
+                        self.send_to_output(ConnectorEvent::Edit { target, message })
                     }
                 }
+                ControllerSignal::OutgoingDelete { target } => {
+                    self.send_to_output(ConnectorEvent::Delete { target })
+                }
+                ControllerSignal::ManageMessages => self.ui.show_own_messages(),
+                ControllerSignal::ChooseReply => self.ui.show_reply_targets(),
+                ControllerSignal::ReplyTo { target } => self.ui.set_reply_to(target),
+                ControllerSignal::ChooseFile => {
+                    if self.encrypted {
+                        self.ui.present_info(tr(Text::AttachEncrypted));
+                    } else {
+                        self.ui.show_file_chooser()
+                    }
+                }
+                ControllerSignal::SendAttachment { path } => self.send_attachment(path),
+                ControllerSignal::ChooseAttachment => self.ui.show_attachments(),
+                ControllerSignal::SaveAttachment { attachment, path } => {
+                    self.save_attachment(attachment, path)
+                }
+                ControllerSignal::ChooseNote => {
+                    if !self.read_only {
+                        self.ui.show_note()
+                    }
+                }
+                ControllerSignal::OutgoingNote { message } => {
+                    if self.may_read_notes() {
+                        self.send_to_output(ConnectorEvent::Note { message })
+                    }
+                }
+                ControllerSignal::TakeOver => self.take_over(),
+                ControllerSignal::ChooseHandBack => self.ui.show_hand_back(),
+                ControllerSignal::HandBack { changes } => self.hand_back(changes),
                 ControllerSignal::Submit => self.ui.submit(),
+                ControllerSignal::Export => self.export_transcript(),
                 ControllerSignal::Quit => self.ui.stop(),
             }
         }
+        if processed > 0 {
+            metrics::add(&metrics::SIGNALS, processed);
+            metrics::observe(&metrics::SIGNAL_LATENCY, started.elapsed());
+        }
         if let Err(mpsc::error::TryRecvError::Disconnected) = self.rx.try_recv() {
             eprintln!("Application crashed!");
         }
     }
 
-    fn init_session(&mut self, con: &mut redis::Connection, session_id: &str) -> Option<()> {
-        let usernames = utils::blocking_get_from_session(con, session_id, "$.username")?;
+    fn stop_typing(&mut self) {
+        if self.typing.stop() {
+            self.send_to_output(ConnectorEvent::Typing { typing: false });
+        }
+    }
+
+    fn on_system_event(&mut self, message: &Message) {
+        let Kind::System(event) = message.kind else {
+            return;
+        };
+        let trusted = message.trust == Trust::Verified
+            && self
+                .roles
+                .get(&message.from)
+                .is_some_and(|role| *role != Role::Customer);
+        if event.closes() && trusted && !self.closed {
+            self.closed = true;
+            self.ui.set_closed(true);
+        }
+    }
+
+    fn may_read_notes(&self) -> bool {
+        self.identity
+            .as_ref()
+            .is_some_and(|identity| notes::visible_to(identity.role))
+    }
+
+    fn may_write_notes(&self, author: &str) -> bool {
+        self.roles
+            .get(author)
+            .is_some_and(|role| notes::visible_to(*role))
+    }
+
+    fn take_over(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if self.closed {
+            self.ui.present_info(tr(Text::ConversationClosed));
+            return;
+        }
+        match self.handoff(None, false) {
+            Ok(()) => self.send_to_output(ConnectorEvent::System {
+                event: SystemEvent::OperatorJoined,
+            }),
+            Err(e) => self
+                .ui
+                .present_info(&format!("{}:\n{}", tr(Text::HandoffFailed), e)),
+        }
+    }
+
+    fn hand_back(&mut self, changes: Option<serde_json::Value>) {
+        if self.read_only {
+            return;
+        }
+        match self.handoff(changes, true) {
+            Ok(()) => self.send_to_output(ConnectorEvent::System {
+                event: SystemEvent::HandedBack,
+            }),
+            Err(e) => self
+                .ui
+                .present_info(&format!("{}:\n{}", tr(Text::HandoffFailed), e)),
+        }
+    }
+
+    fn handoff(&self, changes: Option<serde_json::Value>, back: bool) -> Result<(), StateError> {
+        let actor = self
+            .identity
+            .as_ref()
+            .map(|identity| identity.username.clone())
+            .unwrap_or_default();
+        self.async_runtime.block_on(async {
+            let mut con = try_create_async_redis_connection()
+                .await
+                .map_err(StateError::Redis)?;
+            if back {
+                handoff::hand_back(&mut con, &self.session_id, &actor, changes.as_ref()).await
+            } else {
+                handoff::take_over(&mut con, &self.session_id, &actor).await
+            }
+        })?;
+        Ok(())
+    }
+
+    fn filter_outbound(&mut self, message: String) -> Option<String> {
+        if self.closed {
+            self.ui.refuse(tr(Text::ConversationClosed), Some(message));
+            return None;
+        }
+        match self.pipeline.outbound.outbound(message.clone()) {
+            Ok(message) => Some(message),
+            Err(rejected) => {
+                metrics::increment(&metrics::MESSAGES_REJECTED);
+                let reason = format!(
+                    "{} ({}):\n{}",
+                    tr(Text::MessageRejected),
+                    rejected.by,
+                    rejected.reason
+                );
+                self.ui.refuse(&reason, Some(message));
+                None
+            }
+        }
+    }
+
+    fn send_to_output(&self, event: ConnectorEvent) {
+        if self.read_only {
+            return;
+        }
+        if let Some(output_tx) = self.output_tx.as_ref() {
+            let _ = output_tx.blocking_send(event);
+        }
+    }
+
+    fn init_locale(con: &mut redis::Connection, session_id: &str) {
+        let locale = utils::blocking_get_from_session(con, session_id, "$.locale")
+            .and_then(|locales| utils::extract_one_string_from_array(&locales))
+            .and_then(|tag| Locale::from_tag(&tag))
+            .unwrap_or_else(Locale::from_env);
+        locale::set_locale(locale);
+    }
+
+    fn init_session(
+        &mut self,
+        con: &mut redis::Connection,
+        session_id: &str,
+        role: Role,
+        token: Option<String>,
+    ) -> Option<()> {
+        let mut state = None;
+        let mut username = None;
+        if let Some(session) = utils::blocking_get_from_session(con, session_id, "$")
+            .and_then(|sessions| serde_json::from_value::<Vec<Session>>(sessions).ok())
+            .and_then(|sessions| sessions.into_iter().next())
+        {
+            self.roles = transcript::roles_from_session(&session);
+            self.signing_key = SigningKey::from_env();
+            self.keyring = Keyring::from_session(session_id, &session);
+            self.encrypted = session.encrypted;
+            self.closed = session.state.is_closed();
+            state = Some(session.state);
+            username = Some(session.username_for(role).to_owned());
+        }
+        if self.closed {
+            self.ui.set_closed(true);
+            self.ui.present_info(tr(Text::ConversationClosed));
+        }
+        let usernames = utils::blocking_get_from_session(con, session_id, role.session_path())?;
         let chat_ids = utils::blocking_get_from_session(con, session_id, "$.chat_id")?;
+        let username = username.or_else(|| utils::extract_one_string_from_array(&usernames));
 
+        if let Some(issuer) = IssuerKey::from_env() {
+            let verified = issuer.verify(
+                token.as_deref(),
+                session_id,
+                role,
+                username.as_deref().unwrap_or_default(),
+            );
+            if let Err(e) = verified {
+                return self
+                    .tx
+                    .blocking_send(ControllerSignal::Info {
+                        message: format!("{}:\n{}", tr(Text::AuthFailed), e),
+                    })
+                    .ok();
+            }
+        }
+
+        if role == Role::Operator
+            && !self.read_only
+            && matches!(
+                state,
+                Some(SessionState::Created | SessionState::WaitingOperator | SessionState::Paused)
+            )
+        {
+            let actor = username.clone().unwrap_or_default();
+            let taken = self.async_runtime.block_on(async {
+                let mut con = try_create_async_redis_connection()
+                    .await
+                    .map_err(StateError::Redis)?;
+                handoff::take_over(&mut con, session_id, &actor).await
+            });
+            match taken {
+                Ok(_) => self.announcement = Some(SystemEvent::OperatorJoined),
+                Err(e) => eprintln!("Session {}: {}", session_id, e),
+            }
+        }
         self.tx
             .blocking_send(ControllerSignal::ConnectTo {
-                username: utils::extract_one_string_from_array(&usernames),
+                username,
                 chat_id: utils::extract_one_string_from_array(&chat_ids),
+                role,
             })
             .ok()
     }
 
-    fn connect_to(&mut self, username: &str, chat_id: &str) {
+    fn connect_to(&mut self, username: &str, chat_id: &str, role: Role) {
+        let chat_keys = if self.encrypted && !self.read_only {
+            let Some(identity) = self.signing_key.clone() else {
+                self.ui.present_info(&format!(
+                    "{}:\n{} is not set",
+                    tr(Text::KeyUnavailable),
+                    SIGNING_KEY_VAR
+                ));
+                return;
+            };
+            match KeyPair::load_or_create(chat_id, username) {
+                Ok(keys) => {
+                    let (key_tx, key_rx) = watch::channel(None);
+                    self.async_runtime.handle().spawn(key_exchange(
+                        chat_id.to_owned(),
+                        username.to_owned(),
+                        identity,
+                        self.keyring.clone(),
+                        keys,
+                        key_tx,
+                    ));
+                    Some(key_rx)
+                }
+                Err(e) => {
+                    self.ui
+                        .present_info(&format!("{}:\n{}", tr(Text::KeyUnavailable), e));
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        metrics::gauge(&metrics::ACTIVE_SESSIONS, 1);
         self.ui.change_title(&format!("{} @ {}", username, chat_id));
+        self.ui.set_identity(username, chat_id, role);
+        self.identity = Some(Identity {
+            username: username.to_owned(),
+            chat_id: chat_id.to_owned(),
+            role,
+        });
         let (tx, output_rx) = mpsc::channel(1024);
         self.output_tx = Some(tx);
         self.async_runtime.handle().spawn(output_connector(
-            username.to_owned(),
+            Author {
+                username: username.to_owned(),
+                role,
+                key: self.signing_key.clone(),
+            },
             chat_id.to_owned(),
+            chat_keys.clone(),
             output_rx,
+            self.tx.clone(),
         ));
+        if let Some(event) = self.announcement.take() {
+            self.send_to_output(ConnectorEvent::System { event });
+        }
         self.async_runtime
             .handle()
-            .spawn(input_connector(chat_id.to_owned(), self.tx.clone()));
+            .spawn(receipts_connector(chat_id.to_owned(), self.tx.clone()));
+        self.async_runtime.handle().spawn(typing_connector(
+            chat_id.to_owned(),
+            username.to_owned(),
+            self.tx.clone(),
+        ));
+        self.async_runtime.handle().spawn(input_connector(
+            chat_id.to_owned(),
+            self.keyring.clone(),
+            chat_keys.clone(),
+            self.pipeline.inbound.clone(),
+            self.tx.clone(),
+        ));
+        if notes::visible_to(role) {
+            self.async_runtime.handle().spawn(input_connector(
+                notes::stream_key(chat_id),
+                self.keyring.clone(),
+                chat_keys,
+                self.pipeline.inbound.clone(),
+                self.tx.clone(),
+            ));
+        }
+        self.async_runtime
+            .handle()
+            .spawn(ping_connector(self.tx.clone()));
+        if self.read_only {
+            self.async_runtime
+                .handle()
+                .spawn(participants_connector(chat_id.to_owned(), self.tx.clone()));
+        } else {
+            self.async_runtime.handle().spawn(presence_connector(
+                chat_id.to_owned(),
+                username.to_owned(),
+                role,
+                self.tx.clone(),
+            ));
+        }
+    }
+
+    fn send_attachment(&mut self, path: PathBuf) {
+        let Some(output_tx) = self.output_tx.clone().filter(|_| !self.read_only) else {
+            return;
+        };
+        if self.closed {
+            self.ui.present_info(tr(Text::ConversationClosed));
+            return;
+        }
+        if self.encrypted {
+            self.ui.present_info(tr(Text::AttachEncrypted));
+            return;
+        }
+        let tx = self.tx.clone();
+        self.async_runtime.handle().spawn(async move {
+            let result = match try_create_async_redis_connection().await {
+                Ok(mut con) => attachment::upload(&mut con, &BlobStore::from_env(), &path)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(format!("{}: {:?}", tr(Text::RedisError), e)),
+            };
+            match result {
+                Ok(attachment) => {
+                    let _ = output_tx
+                        .send(ConnectorEvent::Post {
+                            message: attachment.name.clone(),
+                            reply_to: None,
+                            attachment: Some(attachment),
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    let _ = tx
+                        .send(ControllerSignal::Info {
+                            message: format!("{}:\n{}", tr(Text::AttachFailed), e),
+                        })
+                        .await;
+                }
+            }
+        });
+    }
+
+    fn save_attachment(&mut self, attachment: Attachment, path: PathBuf) {
+        let tx = self.tx.clone();
+        self.async_runtime.handle().spawn(async move {
+            let result = match try_create_async_redis_connection().await {
+                Ok(mut con) => {
+                    attachment::download(&mut con, &BlobStore::from_env(), &attachment, &path)
+                        .await
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(format!("{}: {:?}", tr(Text::RedisError), e)),
+            };
+            let message = match result {
+                Ok(()) => format!("{}:\n{}", tr(Text::AttachmentSaved), path.display()),
+                Err(e) => format!("{}:\n{}", tr(Text::SaveFailed), e),
+            };
+            let _ = tx.send(ControllerSignal::Info { message }).await;
+        });
+    }
+
+    fn export_transcript(&mut self) {
+        let Some(chat_id) = self
+            .identity
+            .as_ref()
+            .map(|identity| identity.chat_id.clone())
+        else {
+            return;
+        };
+        let roles = self.roles.clone();
+        let keyring = self.keyring.clone();
+        let tx = self.tx.clone();
+        self.async_runtime.handle().spawn(async move {
+            let format = Format::Markdown;
+            let path =
+                std::path::PathBuf::from(format!("transcript-{}.{}", chat_id, format.extension()));
+            let result = match try_create_async_redis_connection().await {
+                Ok(mut con) => {
+                    transcript::export_to_file(
+                        &mut con,
+                        &chat_id,
+                        &roles,
+                        &keyring,
+                        format,
+                        &ExportOptions::default(),
+                        &path,
+                    )
+                    .await
+                }
+                Err(e) => Err(format!("{}: {:?}", tr(Text::RedisError), e)),
+            };
+            let message = match result {
+                Ok(count) => format!("{} ({}):\n{}", tr(Text::Exported), count, path.display()),
+                Err(e) => format!("{}:\n{}", tr(Text::ExportFailed), e),
+            };
+            let _ = tx.send(ControllerSignal::Info { message }).await;
+        });
     }
 }
 
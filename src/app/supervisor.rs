@@ -0,0 +1,269 @@
+use crate::{
+    auth::{IssuerKey, ISSUER_KEY_VAR},
+    locale::{self, tr, Text},
+    message::{sanitize, Body, Kind, Message},
+    monitor::{self, Chat, MonitorSignal},
+};
+use cursive::{
+    event::Event,
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, SelectView},
+    Cursive, CursiveRunner,
+};
+use tokio::{runtime::Runtime, sync::mpsc};
+
+const CHATS_ID: &str = "chats";
+const STATE_WIDTH: usize = 16;
+const CUSTOMER_WIDTH: usize = 16;
+const SUMMARY_WIDTH: usize = 60;
+
+pub struct Supervisor {
+    runner: CursiveRunner<Cursive>,
+    async_runtime: Runtime,
+    rx: mpsc::Receiver<MonitorSignal>,
+    tx: mpsc::Sender<MonitorSignal>,
+    chats: Vec<Chat>,
+    chosen: Option<Chat>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel(1024);
+        let ncurses =
+            cursive::backends::curses::n::Backend::init().expect("Failed to init ncurses backend.");
+        Self {
+            runner: CursiveRunner::new(Cursive::default(), ncurses),
+            async_runtime: Runtime::new().expect("Failed to start asynchronous runtime."),
+            rx,
+            tx,
+            chats: vec![],
+            chosen: None,
+        }
+    }
+
+    pub fn go(mut self, session_ids: &[String], token: Option<&str>) -> Option<Chat> {
+        let Some(issuer) = IssuerKey::from_env() else {
+            drop(self);
+            eprintln!("{}: {} is not set", tr(Text::AuthFailed), ISSUER_KEY_VAR);
+            return None;
+        };
+        let chats = self.async_runtime.block_on(async {
+            let mut con = crate::connector::try_create_async_redis_connection()
+                .await
+                .ok()?;
+            let session_ids = if session_ids.is_empty() {
+                monitor::discover(&mut con).await.ok()?
+            } else {
+                session_ids.to_vec()
+            };
+            Some(monitor::load(&mut con, &session_ids, &issuer, token).await)
+        });
+        self.chats = chats.unwrap_or_default();
+        if self.chats.is_empty() {
+            drop(self);
+            eprintln!("{}", tr(Text::NoChats));
+            return None;
+        }
+        self.async_runtime
+            .handle()
+            .spawn(monitor::activity_connector(
+                self.chats.clone(),
+                self.tx.clone(),
+            ));
+        self.async_runtime.handle().spawn(monitor::state_connector(
+            self.chats
+                .iter()
+                .map(|chat| chat.session_id.clone())
+                .collect(),
+            self.tx.clone(),
+        ));
+        self.init_view();
+        self.render_chats();
+        while self.runner.is_running() && self.chosen.is_none() {
+            self.process_signals();
+            self.runner.step();
+            self.runner.refresh();
+        }
+        let chosen = self.chosen.take();
+        self.async_runtime
+            .shutdown_timeout(std::time::Duration::from_millis(200));
+        chosen
+    }
+}
+
+impl Supervisor {
+    fn init_view(&mut self) {
+        let tx_ctrl_q = self.tx.clone();
+        self.runner
+            .add_global_callback(Event::CtrlChar('q'), move |_| {
+                let _ = tx_ctrl_q.blocking_send(MonitorSignal::Quit);
+            });
+        let tx_select = self.tx.clone();
+        let tx_open = self.tx.clone();
+        let tx_quit = self.tx.clone();
+        let select = SelectView::<String>::new().on_submit(move |_, session_id: &String| {
+            let _ = tx_select.blocking_send(MonitorSignal::Open {
+                session_id: session_id.clone(),
+            });
+        });
+        self.runner.add_layer(
+            Dialog::around(select.with_name(CHATS_ID).scrollable().full_screen())
+                .title(tr(Text::SupervisorTitle))
+                .button(tr(Text::Open), move |siv| {
+                    let selected = siv
+                        .call_on_name(CHATS_ID, |view: &mut SelectView<String>| view.selection())
+                        .flatten();
+                    if let Some(session_id) = selected {
+                        let _ = tx_open.blocking_send(MonitorSignal::Open {
+                            session_id: (*session_id).clone(),
+                        });
+                    }
+                })
+                .button(tr(Text::Disconnect), move |_| {
+                    let _ = tx_quit.blocking_send(MonitorSignal::Quit);
+                }),
+        );
+    }
+
+    fn process_signals(&mut self) {
+        let mut changed = false;
+        while let Ok(signal) = self.rx.try_recv() {
+            match signal {
+                MonitorSignal::Activity { chat_id, message } => {
+                    if let Some(chat) = self.chats.iter_mut().find(|chat| chat.chat_id == chat_id) {
+                        chat.latest = Some(*message);
+                        changed = true;
+                    }
+                }
+                MonitorSignal::State { session_id, state } => {
+                    if let Some(chat) = self
+                        .chats
+                        .iter_mut()
+                        .find(|chat| chat.session_id == session_id && chat.state != state)
+                    {
+                        chat.state = state;
+                        changed = true;
+                    }
+                }
+                MonitorSignal::Open { session_id } => {
+                    self.chosen = self
+                        .chats
+                        .iter()
+                        .find(|chat| chat.session_id == session_id)
+                        .cloned();
+                }
+                MonitorSignal::Quit => self.runner.quit(),
+            }
+        }
+        if changed {
+            self.render_chats();
+        }
+    }
+
+    fn render_chats(&mut self) {
+        self.chats.sort_by_key(|chat| {
+            std::cmp::Reverse(
+                chat.latest
+                    .as_ref()
+                    .and_then(Message::timestamp_millis)
+                    .unwrap_or_default(),
+            )
+        });
+        let items: Vec<_> = self
+            .chats
+            .iter()
+            .map(|chat| (row(chat), chat.session_id.clone()))
+            .collect();
+        self.runner
+            .call_on_name(CHATS_ID, |view: &mut SelectView<String>| {
+                let selected = view.selection();
+                view.clear();
+                view.add_all(items);
+                let position = selected.and_then(|selected| {
+                    view.iter()
+                        .position(|(_, session_id)| *session_id == *selected)
+                });
+                if let Some(position) = position {
+                    view.set_selection(position);
+                }
+            });
+    }
+}
+
+fn row(chat: &Chat) -> String {
+    let latest = chat
+        .latest
+        .as_ref()
+        .map(|message| {
+            let timestamp = message
+                .timestamp_millis()
+                .map(locale::format_timestamp)
+                .unwrap_or_default();
+            format!("{} {}", timestamp, summary(message))
+        })
+        .unwrap_or_default();
+    format!(
+        "{:<state$} {:<customer$} {}",
+        chat.state.as_str(),
+        clip(&chat.customer, CUSTOMER_WIDTH),
+        clip(&latest, SUMMARY_WIDTH),
+        state = STATE_WIDTH,
+        customer = CUSTOMER_WIDTH,
+    )
+}
+
+fn summary(message: &Message) -> String {
+    if let Kind::System(event) = message.kind {
+        return format!("══ {} ══", event.as_str());
+    }
+    let text = match (&message.body, message.sealed.is_some()) {
+        (_, true) => format!("<{}>", tr(Text::Encrypted)),
+        (Body::Text(text), false) => text.clone(),
+        (Body::Undecodable(_), false) => format!("<{}>", tr(Text::Undecodable)),
+    };
+    format!("[{}] {}", message.from, text)
+}
+
+fn clip(text: &str, width: usize) -> String {
+    let text = sanitize(text).replace('\n', " ");
+    match text.char_indices().nth(width) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ENCRYPTED_FIELD, SYSTEM_FIELD};
+
+    #[test]
+    fn clip_sanitizes_and_shortens() {
+        assert_eq!(clip("one\ntwo", 10), "one two");
+        assert_eq!(clip("\u{1b}[31mabcdef", 3), "abc…");
+    }
+
+    #[test]
+    fn summary_never_shows_sealed_bodies() {
+        let post = Message::from_fields("1000-0", &[("Customer", "hello")]).remove(0);
+        assert_eq!(summary(&post), "[Customer] hello");
+        let sealed = Message::from_fields(
+            "1000-0",
+            &[(ENCRYPTED_FIELD, "1"), ("Customer", "c2VhbGVk")],
+        )
+        .remove(0);
+        assert_eq!(
+            summary(&sealed),
+            format!("[Customer] <{}>", tr(Text::Encrypted))
+        );
+        let system =
+            Message::from_fields("1000-0", &[(SYSTEM_FIELD, "timeout"), ("Robot", "")]).remove(0);
+        assert_eq!(summary(&system), "══ timeout ══");
+    }
+}
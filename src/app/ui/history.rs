@@ -0,0 +1,146 @@
+use crate::{
+    message::{stream_id_cmp, Kind, Message},
+    signing::Trust,
+};
+use std::cmp::Ordering;
+
+pub struct Line {
+    pub message: Message,
+    pub edited: bool,
+    pub deleted: bool,
+}
+
+#[derive(Default)]
+pub struct History {
+    lines: Vec<Line>,
+}
+
+impl History {
+    pub fn push(&mut self, message: Message) -> bool {
+        let position = self
+            .lines
+            .iter()
+            .rposition(|line| stream_id_cmp(&line.message.id, &message.id) != Ordering::Greater)
+            .map_or(0, |n| n + 1);
+        self.lines.insert(
+            position,
+            Line {
+                message,
+                edited: false,
+                deleted: false,
+            },
+        );
+        position == self.lines.len() - 1
+    }
+
+    pub fn edit(&mut self, target: &str, edit: Message) -> bool {
+        match self.find_mut(target, &edit.from) {
+            Some(line)
+                if line.message.trust == Trust::Verified && edit.trust != Trust::Verified =>
+            {
+                false
+            }
+            Some(line) => {
+                line.message.body = edit.body;
+                line.message.trust = edit.trust;
+                line.message.annotations = edit.annotations;
+                line.edited = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn delete(&mut self, target: &str, from: &str) -> bool {
+        match self.find_mut(target, from) {
+            Some(line) => {
+                line.deleted = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Line> {
+        self.lines.iter().find(|line| line.message.id == id)
+    }
+
+    pub fn own<'a>(&'a self, username: &'a str) -> impl Iterator<Item = &'a Line> {
+        self.lines.iter().filter(move |line| {
+            !line.deleted && line.message.kind == Kind::Post && line.message.from == username
+        })
+    }
+
+    fn find_mut(&mut self, target: &str, from: &str) -> Option<&mut Line> {
+        self.lines
+            .iter_mut()
+            .find(|line| !line.deleted && line.message.id == target && line.message.from == from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Body;
+
+    fn message(id: &str, body: &str, trust: Trust) -> Message {
+        let mut message = Message::from_fields(id, &[("Customer", body)]).remove(0);
+        message.trust = trust;
+        message
+    }
+
+    #[test]
+    fn unverified_edit_does_not_replace_a_verified_line() {
+        let mut history = History::default();
+        history.push(message("1-0", "hello", Trust::Verified));
+        assert!(!history.edit("1-0", message("2-0", "forged", Trust::Unsigned)));
+        assert!(!history.edit("1-0", message("2-0", "forged", Trust::Unchecked)));
+        let line = history.find("1-0").unwrap();
+        assert_eq!(line.message.body, Body::Text("hello".to_owned()));
+        assert_eq!(line.message.trust, Trust::Verified);
+        assert!(!line.edited);
+        assert!(history.edit("1-0", message("2-0", "fixed", Trust::Verified)));
+        let line = history.find("1-0").unwrap();
+        assert_eq!(line.message.body, Body::Text("fixed".to_owned()));
+        assert!(line.edited);
+    }
+
+    #[test]
+    fn edits_apply_to_the_author_only() {
+        let mut history = History::default();
+        history.push(message("1-0", "hello", Trust::Unchecked));
+        let mut other = message("2-0", "changed", Trust::Unchecked);
+        other.from = "Operator".to_owned();
+        assert!(!history.edit("1-0", other));
+        assert!(history.edit("1-0", message("2-0", "changed", Trust::Unchecked)));
+    }
+
+    #[test]
+    fn deleted_lines_are_no_longer_own() {
+        let mut history = History::default();
+        history.push(message("1-0", "hello", Trust::Unchecked));
+        assert_eq!(history.own("Customer").count(), 1);
+        assert!(!history.delete("1-0", "Operator"));
+        assert!(history.delete("1-0", "Customer"));
+        assert_eq!(history.own("Customer").count(), 0);
+        assert!(!history.edit("1-0", message("2-0", "too late", Trust::Unchecked)));
+    }
+
+    #[test]
+    fn late_entries_are_placed_by_stream_id() {
+        let mut history = History::default();
+        assert!(history.push(message("1-0", "first", Trust::Unchecked)));
+        assert!(history.push(message("3-0", "third", Trust::Unchecked)));
+        assert!(!history.push(message("2-0", "second", Trust::Unchecked)));
+        let ids: Vec<&str> = history
+            .lines()
+            .iter()
+            .map(|line| line.message.id.as_str())
+            .collect();
+        assert_eq!(ids, ["1-0", "2-0", "3-0"]);
+    }
+}
@@ -1,36 +1,100 @@
-use crate::controller_signals::ControllerSignal;
+use crate::{
+    controller_signals::ControllerSignal,
+    locale::{tr, Text},
+};
 use cursive::{
-    view::{Nameable, Resizable},
-    views::{Dialog, EditView, LinearLayout, TextArea, TextView},
+    view::{Nameable, Resizable, ScrollStrategy, Scrollable},
+    views::{Dialog, EditView, LinearLayout, TextView},
     View,
 };
 use tokio::sync::mpsc;
 
 pub const MAIN_ID: &str = "main";
 pub const VIEW_ID: &str = "view";
+pub const SCROLL_ID: &str = "scroll";
 pub const EDIT_ID: &str = "edit";
+pub const STATUS_ID: &str = "status";
+pub const REPLY_ID: &str = "reply";
+pub const OPTIONS_ID: &str = "options";
+pub const PARTICIPANTS_ID: &str = "participants";
+
+const PARTICIPANTS_WIDTH: usize = 24;
 
-pub fn create_main_view(tx: mpsc::Sender<ControllerSignal>) -> impl View {
+pub fn create_main_view(tx: mpsc::Sender<ControllerSignal>, read_only: bool) -> impl View {
     let tx_submit = tx.clone();
+    let tx_reply = tx.clone();
+    let tx_manage = tx.clone();
+    let tx_attach = tx.clone();
+    let tx_files = tx.clone();
+    let tx_export = tx.clone();
     let tx_quit = tx.clone();
-    Dialog::around(create_main_layout(tx.clone()))
-        .button("Submit", move |_| {
-            let _ = tx_submit.blocking_send(ControllerSignal::Submit);
+    let mut dialog = Dialog::around(create_main_layout(tx.clone(), read_only));
+    if !read_only {
+        dialog = dialog
+            .button(tr(Text::Submit), move |_| {
+                let _ = tx_submit.blocking_send(ControllerSignal::Submit);
+            })
+            .button(tr(Text::Reply), move |_| {
+                let _ = tx_reply.blocking_send(ControllerSignal::ChooseReply);
+            })
+            .button(tr(Text::OwnMessages), move |_| {
+                let _ = tx_manage.blocking_send(ControllerSignal::ManageMessages);
+            })
+            .button(tr(Text::Attach), move |_| {
+                let _ = tx_attach.blocking_send(ControllerSignal::ChooseFile);
+            });
+    }
+    dialog
+        .button(tr(Text::Files), move |_| {
+            let _ = tx_files.blocking_send(ControllerSignal::ChooseAttachment);
         })
-        .button("Disconnect", move |_| {
+        .button(tr(Text::Export), move |_| {
+            let _ = tx_export.blocking_send(ControllerSignal::Export);
+        })
+        .button(tr(Text::Disconnect), move |_| {
             let _ = tx_quit.blocking_send(ControllerSignal::Quit);
         })
-        .title("Main View")
+        .title(tr(Text::MainViewTitle))
         .with_name(MAIN_ID)
 }
 
-fn create_main_layout(tx: mpsc::Sender<ControllerSignal>) -> LinearLayout {
-    let view = TextArea::new().disabled();
-    let edit = EditView::new().on_submit(move |_, _| {
-        let _ = tx.blocking_send(ControllerSignal::Submit);
-    });
-    LinearLayout::vertical()
-        .child(view.with_name(VIEW_ID).full_height())
-        .child(TextView::new("Введите сообщение:"))
+fn create_main_layout(tx: mpsc::Sender<ControllerSignal>, read_only: bool) -> LinearLayout {
+    let view = TextView::new("");
+    let tx_edit = tx.clone();
+    let edit = EditView::new()
+        .on_edit(move |_, content, _| {
+            if !content.is_empty() {
+                let _ = tx_edit.blocking_send(ControllerSignal::Composing);
+            }
+        })
+        .on_submit(move |_, _| {
+            let _ = tx.blocking_send(ControllerSignal::Submit);
+        });
+    let layout = LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    view.with_name(VIEW_ID)
+                        .scrollable()
+                        .scroll_strategy(ScrollStrategy::StickToBottom)
+                        .with_name(SCROLL_ID)
+                        .full_width(),
+                )
+                .child(
+                    TextView::new("")
+                        .with_name(PARTICIPANTS_ID)
+                        .scrollable()
+                        .fixed_width(PARTICIPANTS_WIDTH),
+                )
+                .full_height(),
+        )
+        .child(TextView::new("").with_name(STATUS_ID).full_width());
+    if read_only {
+        return layout;
+    }
+    layout
+        .child(LinearLayout::horizontal().with_name(OPTIONS_ID))
+        .child(TextView::new("").with_name(REPLY_ID).full_width())
+        .child(TextView::new(tr(Text::EnterMessage)))
         .child(edit.with_name(EDIT_ID).full_width())
 }
@@ -0,0 +1,200 @@
+use crate::{
+    attachment::Attachment,
+    controller_signals::ControllerSignal,
+    locale::{tr, Text},
+};
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, SelectView},
+    Cursive, View,
+};
+use tokio::sync::mpsc;
+
+const EDIT_MESSAGE_ID: &str = "edit_message";
+const PATH_ID: &str = "path";
+const CONTEXT_ID: &str = "context";
+const NOTE_ID: &str = "note";
+
+pub fn create_own_messages_view(
+    tx: mpsc::Sender<ControllerSignal>,
+    items: Vec<(String, String, String)>,
+) -> impl View {
+    let mut select = SelectView::<(String, String)>::new();
+    for (label, target, content) in items {
+        select.add_item(label, (target, content));
+    }
+    select.set_on_submit(move |siv, (target, content): &(String, String)| {
+        siv.pop_layer();
+        siv.add_layer(create_edit_message_view(
+            tx.clone(),
+            target.clone(),
+            content,
+        ));
+    });
+    Dialog::around(select.scrollable().max_height(20))
+        .title(tr(Text::OwnMessages))
+        .dismiss_button(tr(Text::Cancel))
+}
+
+pub fn create_reply_targets_view(
+    tx: mpsc::Sender<ControllerSignal>,
+    items: Vec<(String, String)>,
+) -> impl View {
+    let tx_cancel = tx.clone();
+    let mut select = SelectView::<String>::new();
+    select.add_all(items);
+    select.set_on_submit(move |siv, target: &String| {
+        siv.pop_layer();
+        let _ = tx.blocking_send(ControllerSignal::ReplyTo {
+            target: Some(target.clone()),
+        });
+    });
+    Dialog::around(select.scrollable().max_height(20))
+        .title(tr(Text::ReplyTo))
+        .button(tr(Text::CancelReply), move |siv| {
+            siv.pop_layer();
+            let _ = tx_cancel.blocking_send(ControllerSignal::ReplyTo { target: None });
+        })
+        .dismiss_button(tr(Text::Cancel))
+}
+
+pub fn create_file_chooser_view(tx: mpsc::Sender<ControllerSignal>) -> impl View {
+    Dialog::around(EditView::new().with_name(PATH_ID).min_width(50))
+        .title(tr(Text::AttachFile))
+        .button(tr(Text::Send), move |siv| {
+            let path = read_path(siv);
+            siv.pop_layer();
+            if !path.is_empty() {
+                let _ = tx.blocking_send(ControllerSignal::SendAttachment { path: path.into() });
+            }
+        })
+        .dismiss_button(tr(Text::Cancel))
+}
+
+pub fn create_note_view(tx: mpsc::Sender<ControllerSignal>) -> impl View {
+    Dialog::around(EditView::new().with_name(NOTE_ID).min_width(50))
+        .title(tr(Text::NoteTitle))
+        .button(tr(Text::Note), move |siv| {
+            let message = siv
+                .call_on_name(NOTE_ID, |view: &mut EditView| view.get_content())
+                .map(|content| content.trim().to_owned())
+                .unwrap_or_default();
+            siv.pop_layer();
+            if !message.is_empty() {
+                let _ = tx.blocking_send(ControllerSignal::OutgoingNote { message });
+            }
+        })
+        .dismiss_button(tr(Text::Cancel))
+}
+
+pub fn create_hand_back_view(tx: mpsc::Sender<ControllerSignal>) -> impl View {
+    Dialog::around(EditView::new().with_name(CONTEXT_ID).min_width(50))
+        .title(tr(Text::HandBackContext))
+        .button(tr(Text::HandBack), move |siv| {
+            let content = siv
+                .call_on_name(CONTEXT_ID, |view: &mut EditView| view.get_content())
+                .map(|content| content.trim().to_owned())
+                .unwrap_or_default();
+            siv.pop_layer();
+            let signal = if content.is_empty() {
+                ControllerSignal::HandBack { changes: None }
+            } else {
+                match serde_json::from_str::<serde_json::Value>(&content) {
+                    Ok(changes) if changes.is_object() => ControllerSignal::HandBack {
+                        changes: Some(changes),
+                    },
+                    _ => ControllerSignal::Info {
+                        message: tr(Text::InvalidContext).to_owned(),
+                    },
+                }
+            };
+            let _ = tx.blocking_send(signal);
+        })
+        .dismiss_button(tr(Text::Cancel))
+}
+
+pub fn create_attachments_view(
+    tx: mpsc::Sender<ControllerSignal>,
+    items: Vec<(String, Attachment)>,
+) -> impl View {
+    let mut select = SelectView::<Attachment>::new();
+    select.add_all(items);
+    select.set_on_submit(move |siv, attachment: &Attachment| {
+        siv.pop_layer();
+        siv.add_layer(create_save_attachment_view(tx.clone(), attachment.clone()));
+    });
+    Dialog::around(select.scrollable().max_height(20))
+        .title(tr(Text::Files))
+        .dismiss_button(tr(Text::Cancel))
+}
+
+fn create_save_attachment_view(
+    tx: mpsc::Sender<ControllerSignal>,
+    attachment: Attachment,
+) -> impl View {
+    Dialog::around(
+        EditView::new()
+            .content(attachment.name.as_str())
+            .with_name(PATH_ID)
+            .min_width(50),
+    )
+    .title(tr(Text::SaveAs))
+    .button(tr(Text::Save), move |siv| {
+        let path = read_path(siv);
+        siv.pop_layer();
+        if !path.is_empty() {
+            let _ = tx.blocking_send(ControllerSignal::SaveAttachment {
+                attachment: attachment.clone(),
+                path: path.into(),
+            });
+        }
+    })
+    .dismiss_button(tr(Text::Cancel))
+}
+
+fn read_path(siv: &mut Cursive) -> String {
+    siv.call_on_name(PATH_ID, |view: &mut EditView| view.get_content())
+        .map(|content| content.trim().to_owned())
+        .unwrap_or_default()
+}
+
+fn create_edit_message_view(
+    tx: mpsc::Sender<ControllerSignal>,
+    target: String,
+    content: &str,
+) -> impl View {
+    let tx_delete = tx.clone();
+    let target_delete = target.clone();
+    Dialog::around(
+        EditView::new()
+            .content(content)
+            .with_name(EDIT_MESSAGE_ID)
+            .min_width(40),
+    )
+    .title(tr(Text::EditMessage))
+    .button(tr(Text::Save), move |siv| {
+        let message = siv
+            .call_on_name(EDIT_MESSAGE_ID, |view: &mut EditView| view.get_content())
+            .map(|content| content.as_str().to_owned())
+            .unwrap_or_default();
+        siv.pop_layer();
+        let signal = if message.is_empty() {
+            ControllerSignal::Info {
+                message: tr(Text::EmptyMessage).to_owned(),
+            }
+        } else {
+            ControllerSignal::OutgoingEdit {
+                target: target.clone(),
+                message,
+            }
+        };
+        let _ = tx.blocking_send(signal);
+    })
+    .button(tr(Text::Delete), move |siv| {
+        siv.pop_layer();
+        let _ = tx_delete.blocking_send(ControllerSignal::OutgoingDelete {
+            target: target_delete.clone(),
+        });
+    })
+    .dismiss_button(tr(Text::Cancel))
+}
@@ -1,17 +1,50 @@
+mod history;
 mod main;
+mod manage;
+mod render;
+mod status;
+mod unread;
 
-use self::main::{EDIT_ID, MAIN_ID, VIEW_ID};
-use crate::controller_signals::ControllerSignal;
+use self::main::{
+    EDIT_ID, MAIN_ID, OPTIONS_ID, PARTICIPANTS_ID, REPLY_ID, SCROLL_ID, STATUS_ID, VIEW_ID,
+};
+use crate::{
+    connector::ConnectionState,
+    controller_signals::ControllerSignal,
+    locale::{tr, Text},
+    message::{sanitize, Body, Kind, Message},
+    notes,
+    output::{QuickReply, UserOutput},
+    presence::Participant,
+    rate_limit::Rejection,
+    role::Role,
+    typing::TypingState,
+};
 use cursive::{
     event::Event,
-    views::{Dialog, EditView, TextArea, TextView},
+    utils::markup::StyledString,
+    views::{Button, Dialog, DummyView, EditView, LinearLayout, NamedView, ScrollView, TextView},
     Cursive, CursiveRunner,
 };
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 use tokio::sync::mpsc;
 
+const SUMMARY_WIDTH: usize = 40;
+const READ_IDLE: Duration = Duration::from_secs(60);
+
 pub struct Ui {
     runner: CursiveRunner<Cursive>,
     tx: mpsc::Sender<ControllerSignal>,
+    username: String,
+    status: status::Status,
+    history: history::History,
+    reply_to: Option<String>,
+    read: unread::ReadState,
+    last_input: Instant,
+    title: String,
 }
 
 impl Ui {
@@ -20,10 +53,21 @@ impl Ui {
             cursive::backends::curses::n::Backend::init().expect("Failed to init ncurses backend.");
         let runner = CursiveRunner::new(Cursive::default(), ncurses);
 
-        Self { runner, tx }
+        Self {
+            runner,
+            tx,
+            username: String::new(),
+            status: status::Status::new(),
+            history: history::History::default(),
+            reply_to: None,
+            read: unread::ReadState::default(),
+            last_input: Instant::now(),
+            title: String::new(),
+        }
     }
 
-    pub fn init_view(&mut self) {
+    pub fn init_view(&mut self, read_only: bool) {
+        self.status.read_only = read_only;
         let tx_ctrl_q = self.tx.clone();
         self.runner
             .add_global_callback(Event::CtrlChar('q'), move |_| {
@@ -31,14 +75,20 @@ impl Ui {
             });
 
         self.runner
-            .add_layer(main::create_main_view(self.tx.clone()));
+            .add_layer(main::create_main_view(self.tx.clone(), read_only));
 
+        self.update_status();
         self.runner.refresh();
     }
 
     pub fn step_next(&mut self) {
+        if self.status.expire_typing() {
+            self.update_status();
+        }
         if !self.stopped() {
-            self.runner.step();
+            if self.runner.step() {
+                self.last_input = Instant::now();
+            }
             self.runner.refresh();
         }
     }
@@ -52,34 +102,270 @@ impl Ui {
     }
 
     pub fn change_title(&mut self, title: &str) {
+        self.title = title.to_owned();
+        self.update_title();
+    }
+
+    pub fn set_read_cursors(&mut self, cursors: HashMap<String, String>) {
+        if self.read.update(cursors, &self.username) {
+            self.render_chat();
+        }
+        self.update_title();
+    }
+
+    pub fn read_position(&mut self) -> Option<String> {
+        let focused = self.runner.screen().len() == 1 && self.last_input.elapsed() < READ_IDLE;
+        let at_bottom = self
+            .runner
+            .call_on_name(SCROLL_ID, |view: &mut ScrollView<NamedView<TextView>>| {
+                view.is_at_bottom()
+            })
+            .unwrap_or(false);
+        if !focused || !at_bottom {
+            return None;
+        }
+        let latest = self.history.lines().last()?.message.id.clone();
+        if self.read.advance(&latest) {
+            self.update_title();
+            Some(latest)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_identity(&mut self, username: &str, chat_id: &str, role: Role) {
+        self.username = username.to_owned();
+        self.status.chat_id = chat_id.to_owned();
+        self.status.role = Some(role);
+        self.update_status();
+        if notes::visible_to(role) && !self.status.read_only {
+            let tx = self.tx.clone();
+            self.runner.call_on_name(MAIN_ID, |view: &mut Dialog| {
+                view.add_button(tr(Text::Note), move |_| {
+                    let _ = tx.blocking_send(ControllerSignal::ChooseNote);
+                });
+            });
+        }
+        if role == Role::Operator && !self.status.read_only {
+            let tx_take_over = self.tx.clone();
+            let tx_hand_back = self.tx.clone();
+            self.runner.call_on_name(MAIN_ID, |view: &mut Dialog| {
+                view.add_button(tr(Text::TakeOver), move |_| {
+                    let _ = tx_take_over.blocking_send(ControllerSignal::TakeOver);
+                });
+                view.add_button(tr(Text::HandBack), move |_| {
+                    let _ = tx_hand_back.blocking_send(ControllerSignal::ChooseHandBack);
+                });
+            });
+        }
+    }
+
+    pub fn set_connection_state(&mut self, state: ConnectionState) {
+        self.status.state = state;
+        self.update_status();
+    }
+
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.status.latency = Some(latency);
+        self.update_status();
+    }
+
+    pub fn set_closed(&mut self, closed: bool) {
+        self.status.closed = closed;
+        self.update_status();
         self.runner
-            .call_on_name(MAIN_ID, |view: &mut Dialog| view.set_title(title));
-        self.runner.set_window_title(title);
+            .call_on_name(EDIT_ID, |view: &mut EditView| view.set_enabled(!closed));
+        if closed {
+            self.clear_options();
+        }
+    }
+
+    pub fn set_typing(&mut self, state: TypingState) {
+        self.status.set_typing(&state.name, state.typing);
+        self.update_status();
+    }
+
+    pub fn set_participants(&mut self, online: &[Participant], known: &HashMap<String, Role>) {
+        let rendered = render::participants(online, known);
+        self.runner
+            .call_on_name(PARTICIPANTS_ID, |view: &mut TextView| {
+                view.set_content(rendered)
+            });
     }
 
     pub fn submit(&mut self) {
+        if self.status.read_only {
+            return;
+        }
+        if self.status.closed {
+            self.present_info(tr(Text::ConversationClosed));
+            return;
+        }
         let message = self.take_message();
         if message.is_empty() {
             let _ = self.tx.blocking_send(ControllerSignal::Info {
-                message: "You are trying to send an empty message to the chat.\nThis is forbidden."
-                    .to_owned(),
+                message: tr(Text::EmptyMessage).to_owned(),
             });
         } else {
+            let reply_to = self.reply_to.take();
+            self.update_reply();
             let _ = self
                 .tx
-                .blocking_send(ControllerSignal::OutgoingMessage { message });
+                .blocking_send(ControllerSignal::OutgoingMessage { message, reply_to });
+        }
+    }
+
+    pub fn append(&mut self, message: Message) {
+        if message.from == self.username {
+            self.clear_options();
+        } else if let Some(UserOutput::QuickReplies { options, .. }) = message.output.as_ref() {
+            self.set_options(options);
         }
+        let appended = self.history.push(message);
+        let last = self.history.lines().len() - 1;
+        if !appended || self.read.first_new(&self.history, &self.username) == Some(last) {
+            self.render_chat();
+        } else if let Some(rendered) = self
+            .history
+            .lines()
+            .last()
+            .map(|line| self.render_line(line))
+        {
+            self.add_to_chat(rendered);
+        }
+        self.update_title();
     }
 
-    pub fn append(&mut self, from: &str, message: &str) {
-        self.add_line_to_chat(&format!("[{}] -> {}", from, message));
+    pub fn apply_edit(&mut self, target: &str, message: Message) {
+        if self.history.edit(target, message) {
+            self.render_chat();
+        }
     }
 
-    pub fn present_info(&mut self, message: &str) {
+    pub fn apply_delete(&mut self, target: &str, from: &str) {
+        if self.history.delete(target, from) {
+            self.render_chat();
+        }
+    }
+
+    pub fn show_own_messages(&mut self) {
+        let items: Vec<_> = self
+            .history
+            .own(&self.username)
+            .filter_map(|line| match &line.message.body {
+                Body::Text(text) => Some((
+                    render::summary(&line.message, SUMMARY_WIDTH),
+                    line.message.id.clone(),
+                    text.clone(),
+                )),
+                Body::Undecodable(_) => None,
+            })
+            .collect();
+        if items.is_empty() {
+            self.present_info(tr(Text::NoOwnMessages));
+        } else {
+            self.runner
+                .add_layer(manage::create_own_messages_view(self.tx.clone(), items));
+        }
+    }
+
+    pub fn show_reply_targets(&mut self) {
+        let items: Vec<_> = self
+            .history
+            .lines()
+            .iter()
+            .filter(|line| !line.deleted && line.message.kind == Kind::Post)
+            .map(|line| {
+                (
+                    render::summary(&line.message, SUMMARY_WIDTH),
+                    line.message.id.clone(),
+                )
+            })
+            .collect();
+        if items.is_empty() {
+            self.present_info(tr(Text::NothingToReply));
+        } else {
+            self.runner
+                .add_layer(manage::create_reply_targets_view(self.tx.clone(), items));
+        }
+    }
+
+    pub fn set_reply_to(&mut self, target: Option<String>) {
+        self.reply_to = target;
+        self.update_reply();
+    }
+
+    pub fn show_file_chooser(&mut self) {
+        self.runner
+            .add_layer(manage::create_file_chooser_view(self.tx.clone()));
+    }
+
+    pub fn show_note(&mut self) {
+        self.runner
+            .add_layer(manage::create_note_view(self.tx.clone()));
+    }
+
+    pub fn show_hand_back(&mut self) {
         self.runner
-            .add_layer(Dialog::around(TextView::new(message)).button("OK", |siv| {
+            .add_layer(manage::create_hand_back_view(self.tx.clone()));
+    }
+
+    pub fn show_attachments(&mut self) {
+        let items: Vec<_> = self
+            .history
+            .lines()
+            .iter()
+            .filter(|line| !line.deleted)
+            .filter_map(|line| {
+                line.message.attachment.clone().map(|attachment| {
+                    (
+                        format!("{} {}", render::label(&line.message), attachment.describe()),
+                        attachment,
+                    )
+                })
+            })
+            .collect();
+        if items.is_empty() {
+            self.present_info(tr(Text::NoAttachments));
+        } else {
+            self.runner
+                .add_layer(manage::create_attachments_view(self.tx.clone(), items));
+        }
+    }
+
+    pub fn clear_options(&mut self) {
+        self.runner
+            .call_on_name(OPTIONS_ID, |view: &mut LinearLayout| view.clear());
+    }
+
+    pub fn reject(&mut self, rejection: Rejection, draft: Option<String>) {
+        let message = match rejection {
+            Rejection::RateLimited => tr(Text::RateLimited).to_owned(),
+            Rejection::TooLarge { size, limit } => {
+                format!("{} ({} > {})", tr(Text::MessageTooLarge), size, limit)
+            }
+            Rejection::Unavailable => tr(Text::RedisError).to_owned(),
+        };
+        self.refuse(&message, draft);
+    }
+
+    pub fn refuse(&mut self, message: &str, draft: Option<String>) {
+        if let Some(draft) = draft {
+            self.runner.call_on_name(EDIT_ID, |view: &mut EditView| {
+                if view.get_content().is_empty() {
+                    view.set_content(draft);
+                }
+            });
+        }
+        self.present_info(message);
+    }
+
+    pub fn present_info(&mut self, message: &str) {
+        self.runner.add_layer(
+            Dialog::around(TextView::new(message)).button(tr(Text::Ok), |siv| {
                 siv.pop_layer();
-            }))
+            }),
+        )
     }
 }
 
@@ -96,11 +382,92 @@ impl Ui {
         content
     }
 
-    fn add_line_to_chat(&mut self, line: &str) {
+    fn update_status(&mut self) {
+        let rendered = self.status.render();
+        self.runner
+            .call_on_name(STATUS_ID, |view: &mut TextView| view.set_content(rendered));
+    }
+
+    fn render_line(&self, line: &history::Line) -> StyledString {
+        let parent = line
+            .message
+            .reply_to
+            .as_deref()
+            .and_then(|target| self.history.find(target));
+        render::line(line, parent, line.message.from == self.username)
+    }
+
+    fn set_options(&mut self, options: &[QuickReply]) {
+        let tx = self.tx.clone();
+        self.runner
+            .call_on_name(OPTIONS_ID, |view: &mut LinearLayout| {
+                view.clear();
+                for option in options {
+                    let tx = tx.clone();
+                    let value = option.value().to_owned();
+                    view.add_child(Button::new(sanitize(&option.label), move |_| {
+                        let _ = tx.blocking_send(ControllerSignal::QuickReply {
+                            value: value.clone(),
+                        });
+                    }));
+                    view.add_child(DummyView);
+                }
+            });
+    }
+
+    fn update_reply(&mut self) {
+        let rendered = match self.reply_to.as_deref() {
+            Some(target) => format!(
+                "{} {}",
+                tr(Text::ReplyingTo),
+                render::quote(self.history.find(target))
+            ),
+            None => String::new(),
+        };
+        self.runner
+            .call_on_name(REPLY_ID, |view: &mut TextView| view.set_content(rendered));
+    }
+
+    fn update_title(&mut self) {
+        let unread = self.read.unread_count(&self.history, &self.username);
+        let title = if unread > 0 {
+            format!("({}) {}", unread, self.title)
+        } else {
+            self.title.clone()
+        };
+        self.runner
+            .call_on_name(MAIN_ID, |view: &mut Dialog| view.set_title(title.as_str()));
+        self.runner.set_window_title(title);
+    }
+
+    fn render_chat(&mut self) {
+        let first_new = self.read.first_new(&self.history, &self.username);
+        let seen = self.read.seen_by(&self.history, &self.username);
+        let mut rendered = StyledString::new();
+        for (n, line) in self.history.lines().iter().enumerate() {
+            if n > 0 {
+                rendered.append_plain("\n");
+            }
+            if first_new == Some(n) {
+                rendered.append(render::new_messages_divider());
+                rendered.append_plain("\n");
+            }
+            rendered.append(self.render_line(line));
+            if let Some(readers) = seen.get(&line.message.id) {
+                rendered.append(render::seen_by(readers));
+            }
+        }
+        self.runner
+            .call_on_name(VIEW_ID, |view: &mut TextView| view.set_content(rendered));
+    }
+
+    fn add_to_chat(&mut self, rendered: StyledString) {
         self.runner
-            .call_on_name(VIEW_ID, |view: &mut TextArea| {
-                let content = view.get_content();
-                view.set_content(&format!("{}\n{}", content, line))
+            .call_on_name(VIEW_ID, |view: &mut TextView| {
+                if !view.get_content().source().is_empty() {
+                    view.append("\n");
+                }
+                view.append(rendered)
             })
             .unwrap();
     }
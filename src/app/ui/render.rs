@@ -0,0 +1,362 @@
+use super::{history::Line, status::role_text};
+use crate::{
+    locale::{self, tr, Text},
+    message::{sanitize, Body, Kind, Message},
+    output::UserOutput,
+    presence::{Participant, PresenceEvent},
+    role::Role,
+    signing::Trust,
+    system::SystemEvent,
+};
+use cursive::{
+    theme::{BaseColor, Color, Effect, Style},
+    utils::markup::StyledString,
+};
+use std::collections::HashMap;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const AUTHOR_WIDTH: usize = 24;
+const QUOTE_WIDTH: usize = 48;
+const QUOTE_COLOR: Color = Color::Light(BaseColor::Black);
+const BODY_INDENT: &str = "  ";
+
+pub fn line(line: &Line, parent: Option<&Line>, own: bool) -> StyledString {
+    if let Kind::Presence(event) = line.message.kind {
+        return presence(&line.message, event);
+    }
+    if let Kind::System(event) = line.message.kind {
+        return system(&line.message, event);
+    }
+    if line.message.kind == Kind::Note {
+        return note(&line.message);
+    }
+    let mut rendered = header(&line.message, own);
+    if line.message.reply_to.is_some() {
+        rendered.append_plain(format!("\n{}", BODY_INDENT));
+        rendered.append_styled(
+            quote(parent),
+            Style::from(Effect::Italic).combine(QUOTE_COLOR),
+        );
+    }
+    if line.deleted {
+        rendered.append_plain(format!("\n{}", BODY_INDENT));
+        rendered.append_styled(format!("<{}>", tr(Text::Deleted)), Effect::Italic);
+        return rendered;
+    }
+    match line.message.output.as_ref() {
+        Some(output) if !line.edited => rendered.append(user_output(output)),
+        _ => rendered.append(body(&line.message.body)),
+    }
+    if let Some(attachment) = line.message.attachment.as_ref() {
+        rendered.append_plain(format!("\n{}", BODY_INDENT));
+        rendered.append_styled(
+            format!("📎 {}", sanitize_line(&attachment.describe())),
+            Style::from(Effect::Underline).combine(Color::Dark(BaseColor::Cyan)),
+        );
+    }
+    if !line.message.annotations.is_empty() {
+        let annotations: Vec<_> = line
+            .message
+            .annotations
+            .iter()
+            .map(|annotation| sanitize_line(annotation))
+            .collect();
+        rendered.append_plain(format!("\n{}", BODY_INDENT));
+        rendered.append_styled(format!("ⓘ {}", annotations.join(" · ")), QUOTE_COLOR);
+    }
+    if line.edited {
+        rendered.append_styled(format!(" ({})", tr(Text::Edited)), Effect::Italic);
+    }
+    rendered
+}
+
+pub fn quote(parent: Option<&Line>) -> String {
+    match parent {
+        Some(parent) if !parent.deleted => format!(
+            "↳ [{}] {}",
+            truncate_to_width(&sanitize_line(&parent.message.from), AUTHOR_WIDTH),
+            summary_text(&parent.message.body, QUOTE_WIDTH)
+        ),
+        Some(_) => format!("↳ <{}>", tr(Text::Deleted)),
+        None => format!("↳ <{}>", tr(Text::ReplyUnavailable)),
+    }
+}
+
+pub fn participants(online: &[Participant], known: &HashMap<String, Role>) -> StyledString {
+    let mut rendered = StyledString::styled(tr(Text::Participants), Effect::Bold);
+    for participant in online {
+        rendered.append_styled("\n● ", Color::Dark(BaseColor::Green));
+        rendered.append_plain(truncate_to_width(
+            &sanitize_line(&participant.name),
+            AUTHOR_WIDTH - 2,
+        ));
+        rendered.append_styled(format!("\n  {}", role_text(participant.role)), QUOTE_COLOR);
+    }
+    let mut offline: Vec<_> = known
+        .iter()
+        .filter(|(name, _)| !online.iter().any(|participant| &participant.name == *name))
+        .collect();
+    offline.sort_by_key(|(name, _)| *name);
+    for (name, role) in offline {
+        rendered.append_styled("\n○ ", QUOTE_COLOR);
+        rendered.append_styled(
+            truncate_to_width(&sanitize_line(name), AUTHOR_WIDTH - 2),
+            QUOTE_COLOR,
+        );
+        rendered.append_styled(format!("\n  {}", role_text(*role)), QUOTE_COLOR);
+    }
+    rendered
+}
+
+pub fn new_messages_divider() -> StyledString {
+    StyledString::styled(
+        format!("──── {} ────", tr(Text::NewMessages)),
+        Style::from(Effect::Bold).combine(Color::Dark(BaseColor::Red)),
+    )
+}
+
+pub fn seen_by(readers: &[String]) -> StyledString {
+    let readers: Vec<_> = readers.iter().map(|reader| sanitize_line(reader)).collect();
+    StyledString::styled(
+        format!(
+            "\n{}✓ {} {}",
+            BODY_INDENT,
+            tr(Text::SeenBy),
+            readers.join(", ")
+        ),
+        Style::from(Effect::Italic).combine(QUOTE_COLOR),
+    )
+}
+
+fn presence(message: &Message, event: PresenceEvent) -> StyledString {
+    let role = message
+        .body
+        .as_text()
+        .and_then(|role| role.parse::<Role>().ok())
+        .map(role_text)
+        .unwrap_or_default();
+    let (arrow, text) = match event {
+        PresenceEvent::Join => ("→", tr(Text::Joined)),
+        PresenceEvent::Leave => ("←", tr(Text::Left)),
+    };
+    let timestamp = message
+        .timestamp_millis()
+        .map(locale::format_timestamp)
+        .unwrap_or_default();
+    StyledString::styled(
+        format!(
+            "{} {} {} {} ({})",
+            timestamp,
+            arrow,
+            truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH),
+            text,
+            role
+        ),
+        Style::from(Effect::Italic).combine(QUOTE_COLOR),
+    )
+}
+
+fn system(message: &Message, event: SystemEvent) -> StyledString {
+    let text = match event {
+        SystemEvent::BotJoined | SystemEvent::OperatorJoined => format!(
+            "{} {}",
+            truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH),
+            tr(Text::SystemJoined)
+        ),
+        SystemEvent::OperatorRequested => tr(Text::SystemOperatorRequested).to_owned(),
+        SystemEvent::BotResumed => format!(
+            "{} {}",
+            truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH),
+            tr(Text::SystemResumed)
+        ),
+        SystemEvent::HandedBack => format!(
+            "{} {}",
+            truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH),
+            tr(Text::SystemHandedBack)
+        ),
+        SystemEvent::Finished => tr(Text::SystemFinished).to_owned(),
+        SystemEvent::Timeout => tr(Text::SystemTimeout).to_owned(),
+    };
+    let timestamp = message
+        .timestamp_millis()
+        .map(locale::format_timestamp)
+        .unwrap_or_default();
+    StyledString::styled(
+        format!("══ {} {} ══", timestamp, text),
+        Style::from(Effect::Bold).combine(Color::Dark(BaseColor::Magenta)),
+    )
+}
+
+fn user_output(output: &UserOutput) -> StyledString {
+    let mut rendered = StyledString::new();
+    match output {
+        UserOutput::Text { text } => rendered.append(body(&Body::Text(text.clone()))),
+        UserOutput::QuickReplies { text, options } => {
+            rendered.append(body(&Body::Text(text.clone())));
+            rendered.append_plain(format!("\n{}", BODY_INDENT));
+            for option in options {
+                rendered.append_styled(
+                    format!("[{}]", sanitize_line(&option.label)),
+                    Style::from(Effect::Bold).combine(Color::Dark(BaseColor::Magenta)),
+                );
+                rendered.append_plain(" ");
+            }
+        }
+        UserOutput::Card {
+            title,
+            text,
+            fields,
+        } => {
+            rendered.append_plain(format!("\n{}┌ ", BODY_INDENT));
+            rendered.append_styled(sanitize_line(title), Effect::Bold);
+            if let Some(text) = text {
+                for line in sanitize(text).lines() {
+                    rendered.append_plain(format!("\n{}│ {}", BODY_INDENT, line));
+                }
+            }
+            for field in fields {
+                rendered.append_plain(format!("\n{}│ ", BODY_INDENT));
+                rendered.append_styled(format!("{}: ", sanitize_line(&field.name)), Effect::Bold);
+                rendered.append_plain(sanitize_line(&field.value));
+            }
+            rendered.append_plain(format!("\n{}└", BODY_INDENT));
+        }
+    }
+    rendered
+}
+
+fn body(body: &Body) -> StyledString {
+    let mut rendered = StyledString::new();
+    match body {
+        Body::Text(text) => {
+            for line in sanitize(text).lines() {
+                rendered.append_plain(format!("\n{}{}", BODY_INDENT, line));
+            }
+        }
+        Body::Undecodable(details) => {
+            rendered.append_plain(format!("\n{}", BODY_INDENT));
+            rendered.append_styled(
+                format!("<{}: {}>", tr(Text::Undecodable), sanitize(details)),
+                Style::from(Effect::Italic).combine(Color::Dark(BaseColor::Red)),
+            );
+        }
+    }
+    rendered
+}
+
+fn note(message: &Message) -> StyledString {
+    let color = Color::Dark(BaseColor::Yellow);
+    let mut rendered = StyledString::styled(
+        format!(
+            "✎ {} [{}]",
+            tr(Text::NoteLabel),
+            truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH)
+        ),
+        Style::from(Effect::Bold).combine(color),
+    );
+    if let Some(millis) = message.timestamp_millis() {
+        rendered.append_styled(
+            format!(" {}", locale::format_timestamp(millis)),
+            QUOTE_COLOR,
+        );
+    }
+    if message.trust == Trust::Unsigned {
+        rendered.append_styled(
+            format!(" ⚠ {}", tr(Text::Unverified)),
+            Style::from(Effect::Bold).combine(Color::Dark(BaseColor::Red)),
+        );
+    }
+    for line in sanitize(message.body.as_text().unwrap_or_default()).lines() {
+        rendered.append_styled(
+            format!("\n{}{}", BODY_INDENT, line),
+            Style::from(Effect::Italic).combine(color),
+        );
+    }
+    rendered
+}
+
+fn header(message: &Message, own: bool) -> StyledString {
+    let author_color = if own {
+        Color::Dark(BaseColor::Blue)
+    } else {
+        Color::Dark(BaseColor::Green)
+    };
+    let mut header = StyledString::styled(
+        format!(
+            "[{}]",
+            truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH)
+        ),
+        Style::from(Effect::Bold).combine(author_color),
+    );
+    if let Some(millis) = message.timestamp_millis() {
+        header.append_styled(
+            format!(" {}", locale::format_timestamp(millis)),
+            Color::Light(BaseColor::Black),
+        );
+    }
+    if let Some(original) = message.replay_of.as_deref() {
+        header.append_styled(
+            format!(
+                " ↻ {} {}",
+                tr(Text::ReplayOf),
+                truncate_to_width(&sanitize_line(original), AUTHOR_WIDTH)
+            ),
+            Effect::Italic,
+        );
+    }
+    if message.trust == Trust::Unsigned {
+        header.append_styled(
+            format!(" ⚠ {}", tr(Text::Unverified)),
+            Style::from(Effect::Bold).combine(Color::Dark(BaseColor::Red)),
+        );
+    }
+    header
+}
+
+pub fn summary(message: &Message, max_width: usize) -> String {
+    format!(
+        "{} {}",
+        label(message),
+        summary_text(&message.body, max_width)
+    )
+}
+
+pub fn label(message: &Message) -> String {
+    let timestamp = message
+        .timestamp_millis()
+        .map(locale::format_timestamp)
+        .unwrap_or_default();
+    format!(
+        "{} [{}]",
+        timestamp,
+        truncate_to_width(&sanitize_line(&message.from), AUTHOR_WIDTH)
+    )
+}
+
+fn summary_text(body: &Body, max_width: usize) -> String {
+    match body {
+        Body::Text(text) => truncate_to_width(&sanitize_line(text), max_width),
+        Body::Undecodable(_) => format!("<{}>", tr(Text::Undecodable)),
+    }
+}
+
+fn sanitize_line(text: &str) -> String {
+    sanitize(text).replace('\n', " ")
+}
+
+fn truncate_to_width(text: &str, max_width: usize) -> String {
+    if text.width() <= max_width {
+        return text.to_owned();
+    }
+    let mut width = 0;
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        width += c.width().unwrap_or(0);
+        if width >= max_width {
+            break;
+        }
+        result.push(c);
+    }
+    result.push('…');
+    result
+}
@@ -0,0 +1,146 @@
+use crate::{
+    connector::ConnectionState,
+    locale::{tr, Text},
+    message::sanitize,
+    role::Role,
+    typing::TYPING_EXPIRY,
+};
+use cursive::{
+    theme::{BaseColor, Color, Effect, Style},
+    utils::markup::StyledString,
+};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+pub struct Status {
+    pub state: ConnectionState,
+    pub chat_id: String,
+    pub role: Option<Role>,
+    pub latency: Option<Duration>,
+    pub typing: BTreeMap<String, Instant>,
+    pub closed: bool,
+    pub read_only: bool,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Self {
+            state: ConnectionState::Connecting,
+            chat_id: String::new(),
+            role: None,
+            latency: None,
+            typing: BTreeMap::new(),
+            closed: false,
+            read_only: false,
+        }
+    }
+
+    pub fn set_typing(&mut self, name: &str, typing: bool) {
+        if typing {
+            self.typing
+                .insert(name.to_owned(), Instant::now() + TYPING_EXPIRY);
+        } else {
+            self.typing.remove(name);
+        }
+    }
+
+    pub fn expire_typing(&mut self) -> bool {
+        let now = Instant::now();
+        let before = self.typing.len();
+        self.typing.retain(|_, expires| *expires > now);
+        self.typing.len() != before
+    }
+
+    pub fn render(&self) -> StyledString {
+        let (state, color) = match self.state {
+            ConnectionState::Connecting => (Text::StateConnecting, BaseColor::Yellow),
+            ConnectionState::Online => (Text::StateOnline, BaseColor::Green),
+            ConnectionState::Reconnecting => (Text::StateReconnecting, BaseColor::Yellow),
+            ConnectionState::Offline => (Text::StateOffline, BaseColor::Red),
+        };
+        let mut status = StyledString::styled(
+            format!("● {}", tr(state)),
+            Style::from(Effect::Bold).combine(Color::Dark(color)),
+        );
+        if !self.chat_id.is_empty() {
+            status.append_plain(format!(" | {}: {}", tr(Text::Chat), self.chat_id));
+        }
+        if let Some(role) = self.role {
+            status.append_plain(format!(" | {}: {}", tr(Text::Role), role_text(role)));
+        }
+        let latency = match (self.state, self.latency) {
+            (ConnectionState::Online, Some(latency)) => format!("{} ms", latency.as_millis()),
+            _ => "—".to_owned(),
+        };
+        status.append_plain(format!(" | {}: {}", tr(Text::Latency), latency));
+        if self.read_only {
+            status.append_plain(" | ");
+            status.append_styled(tr(Text::ReadOnly), Effect::Bold);
+        }
+        if self.closed {
+            status.append_plain(" | ");
+            status.append_styled(
+                tr(Text::ConversationClosed),
+                Style::from(Effect::Bold).combine(Color::Dark(BaseColor::Red)),
+            );
+        }
+        if !self.typing.is_empty() {
+            let names: Vec<_> = self.typing.keys().map(|name| sanitize(name)).collect();
+            let text = if names.len() == 1 {
+                tr(Text::IsTyping)
+            } else {
+                tr(Text::AreTyping)
+            };
+            status.append_styled(format!(" | {} {}", names.join(", "), text), Effect::Italic);
+        }
+        status
+    }
+}
+
+pub fn role_text(role: Role) -> &'static str {
+    tr(match role {
+        Role::Customer => Text::RoleCustomer,
+        Role::Robot => Text::RoleRobot,
+        Role::Operator => Text::RoleOperator,
+        Role::Supervisor => Text::RoleSupervisor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_is_shown_only_while_online() {
+        let mut status = Status::new();
+        status.chat_id = "chat".to_owned();
+        status.latency = Some(Duration::from_millis(12));
+        assert!(status.render().source().ends_with(": —"));
+        status.state = ConnectionState::Online;
+        let rendered = status.render();
+        assert!(rendered.source().ends_with(": 12 ms"));
+        assert!(rendered.source().contains(": chat |"));
+    }
+
+    #[test]
+    fn typing_names_are_listed_until_they_expire() {
+        let mut status = Status::new();
+        status.set_typing("Bob", true);
+        status.set_typing("Alice", true);
+        assert!(status
+            .render()
+            .source()
+            .ends_with(&format!("Alice, Bob {}", tr(Text::AreTyping))));
+        status.set_typing("Bob", false);
+        assert!(status
+            .render()
+            .source()
+            .ends_with(&format!("Alice {}", tr(Text::IsTyping))));
+        assert!(!status.expire_typing());
+        status.typing.insert("Alice".to_owned(), Instant::now());
+        assert!(status.expire_typing());
+        assert!(status.typing.is_empty());
+    }
+}
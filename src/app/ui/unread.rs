@@ -0,0 +1,143 @@
+use super::history::{History, Line};
+use crate::message::{stream_id_cmp, Kind};
+use std::{cmp::Ordering, collections::HashMap};
+
+const BEGINNING: &str = "0-0";
+
+#[derive(Default)]
+pub struct ReadState {
+    cursors: HashMap<String, String>,
+    own: Option<String>,
+    divider: Option<String>,
+}
+
+impl ReadState {
+    pub fn update(&mut self, cursors: HashMap<String, String>, username: &str) -> bool {
+        let own = cursors.get(username).cloned();
+        if self.divider.is_none() {
+            self.divider = Some(own.clone().unwrap_or_else(|| BEGINNING.to_owned()));
+        }
+        if let Some(own) = own {
+            self.advance(&own);
+        }
+        let changed = self.cursors != cursors;
+        self.cursors = cursors;
+        changed
+    }
+
+    pub fn advance(&mut self, stream_id: &str) -> bool {
+        let advances = self
+            .own
+            .as_deref()
+            .is_none_or(|own| stream_id_cmp(stream_id, own) == Ordering::Greater);
+        if advances {
+            self.own = Some(stream_id.to_owned());
+        }
+        advances
+    }
+
+    pub fn is_unread(&self, line: &Line, username: &str) -> bool {
+        is_incoming(line, username)
+            && self
+                .own
+                .as_deref()
+                .is_none_or(|own| stream_id_cmp(&line.message.id, own) == Ordering::Greater)
+    }
+
+    pub fn unread_count(&self, history: &History, username: &str) -> usize {
+        history
+            .lines()
+            .iter()
+            .filter(|line| self.is_unread(line, username))
+            .count()
+    }
+
+    pub fn first_new(&self, history: &History, username: &str) -> Option<usize> {
+        let divider = self.divider.as_deref()?;
+        history.lines().iter().position(|line| {
+            is_incoming(line, username)
+                && stream_id_cmp(&line.message.id, divider) == Ordering::Greater
+        })
+    }
+
+    pub fn seen_by(&self, history: &History, username: &str) -> HashMap<String, Vec<String>> {
+        let mut seen: HashMap<String, Vec<String>> = HashMap::new();
+        for (reader, cursor) in self
+            .cursors
+            .iter()
+            .filter(|(reader, _)| *reader != username)
+        {
+            let last_seen = history
+                .own(username)
+                .filter(|line| stream_id_cmp(&line.message.id, cursor) != Ordering::Greater);
+            if let Some(line) = last_seen.last() {
+                seen.entry(line.message.id.clone())
+                    .or_default()
+                    .push(reader.clone());
+            }
+        }
+        for readers in seen.values_mut() {
+            readers.sort();
+        }
+        seen
+    }
+}
+
+fn is_incoming(line: &Line, username: &str) -> bool {
+    !line.deleted && line.message.kind == Kind::Post && line.message.from != username
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    fn history() -> History {
+        let mut history = History::default();
+        for (id, from) in [
+            ("1-0", "Customer"),
+            ("2-0", "Operator"),
+            ("3-0", "Operator"),
+        ] {
+            history.push(Message::from_fields(id, &[(from, "text")]).remove(0));
+        }
+        history
+    }
+
+    #[test]
+    fn read_cursor_only_moves_forward() {
+        let mut state = ReadState::default();
+        assert!(state.advance("2-0"));
+        assert!(!state.advance("1-0"));
+        assert!(!state.advance("2-0"));
+        assert!(state.advance("10-0"));
+    }
+
+    #[test]
+    fn counts_unread_incoming_messages_after_the_cursor() {
+        let history = history();
+        let mut state = ReadState::default();
+        state.update(
+            HashMap::from([("Customer".to_owned(), "2-0".to_owned())]),
+            "Customer",
+        );
+        assert_eq!(state.unread_count(&history, "Customer"), 1);
+        assert_eq!(state.first_new(&history, "Customer"), Some(2));
+    }
+
+    #[test]
+    fn seen_by_marks_the_last_own_message_a_reader_reached() {
+        let history = history();
+        let mut state = ReadState::default();
+        state.update(
+            HashMap::from([
+                ("Customer".to_owned(), "2-0".to_owned()),
+                ("Robot".to_owned(), "5-0".to_owned()),
+            ]),
+            "Operator",
+        );
+        let seen = state.seen_by(&history, "Operator");
+        assert_eq!(seen.get("2-0"), Some(&vec!["Customer".to_owned()]));
+        assert_eq!(seen.get("3-0"), Some(&vec!["Robot".to_owned()]));
+    }
+}
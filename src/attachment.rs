@@ -0,0 +1,286 @@
+use redis::AsyncCommands;
+use sha2::{Digest, Sha256};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub const MAX_ATTACHMENT_SIZE: usize = 10 * 1024 * 1024;
+pub const BLOB_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const BLOB_DIR_VAR: &str = "TUI_CHAT_BLOB_DIR";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BlobStore {
+    Redis,
+    Directory { path: PathBuf },
+}
+
+impl BlobStore {
+    pub fn from_env() -> Self {
+        match std::env::var(BLOB_DIR_VAR) {
+            Ok(path) if !path.is_empty() => Self::Directory { path: path.into() },
+            _ => Self::Redis,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    pub content_type: String,
+    pub size: usize,
+    pub sha256: String,
+    pub chunks: usize,
+    pub store: BlobStore,
+}
+
+#[derive(Debug)]
+pub enum AttachmentError {
+    TooLarge { size: usize, limit: usize },
+    Io(std::io::Error),
+    Redis(redis::RedisError),
+    Missing,
+    ChecksumMismatch,
+}
+
+impl std::fmt::Display for AttachmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { size, limit } => {
+                write!(f, "file is {} bytes, the limit is {} bytes", size, limit)
+            }
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Redis(e) => write!(f, "{}", e),
+            Self::Missing => write!(f, "attachment is no longer available"),
+            Self::ChecksumMismatch => write!(f, "attachment checksum does not match"),
+        }
+    }
+}
+
+impl From<std::io::Error> for AttachmentError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<redis::RedisError> for AttachmentError {
+    fn from(e: redis::RedisError) -> Self {
+        Self::Redis(e)
+    }
+}
+
+impl Attachment {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} ({}, {})",
+            self.name,
+            self.content_type,
+            human_size(self.size)
+        )
+    }
+}
+
+pub async fn upload(
+    con: &mut redis::aio::MultiplexedConnection,
+    store: &BlobStore,
+    path: &Path,
+) -> Result<Attachment, AttachmentError> {
+    let data = read_limited(path)?;
+    let attachment = Attachment {
+        id: format!("{}", uuid::Uuid::new_v4()),
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_owned()),
+        content_type: content_type(path).to_owned(),
+        size: data.len(),
+        sha256: checksum(&data),
+        chunks: data.chunks(CHUNK_SIZE).count(),
+        store: store.clone(),
+    };
+    match store {
+        BlobStore::Redis => {
+            let key = blob_key(&attachment.id);
+            for chunk in data.chunks(CHUNK_SIZE) {
+                let _: () = con.rpush(&key, chunk).await?;
+            }
+            let _: () = con.expire(&key, BLOB_TTL.as_secs() as i64).await?;
+        }
+        BlobStore::Directory { path } => {
+            std::fs::create_dir_all(path)?;
+            std::fs::write(path.join(&attachment.id), &data)?;
+        }
+    }
+    Ok(attachment)
+}
+
+pub async fn download(
+    con: &mut redis::aio::MultiplexedConnection,
+    local: &BlobStore,
+    attachment: &Attachment,
+    destination: &Path,
+) -> Result<(), AttachmentError> {
+    if uuid::Uuid::parse_str(&attachment.id).is_err() {
+        return Err(AttachmentError::Missing);
+    }
+    if attachment.size > MAX_ATTACHMENT_SIZE {
+        return Err(AttachmentError::TooLarge {
+            size: attachment.size,
+            limit: MAX_ATTACHMENT_SIZE,
+        });
+    }
+    let data = match (&attachment.store, local) {
+        (BlobStore::Redis, _) => {
+            let key = blob_key(&attachment.id);
+            let chunks: usize = con.llen(&key).await?;
+            if chunks != attachment.chunks {
+                return Err(AttachmentError::Missing);
+            }
+            let mut data = vec![];
+            for index in 0..chunks {
+                let chunk: Vec<u8> = con.lindex(&key, index as isize).await?;
+                data.extend_from_slice(&chunk);
+                if data.len() > MAX_ATTACHMENT_SIZE {
+                    return Err(AttachmentError::TooLarge {
+                        size: data.len(),
+                        limit: MAX_ATTACHMENT_SIZE,
+                    });
+                }
+            }
+            data
+        }
+        (BlobStore::Directory { path }, BlobStore::Directory { path: own })
+            if same_directory(path, own) =>
+        {
+            match read_limited(&own.join(&attachment.id)) {
+                Ok(data) => data,
+                Err(AttachmentError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(AttachmentError::Missing)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        (BlobStore::Directory { .. }, _) => return Err(AttachmentError::Missing),
+    };
+    if checksum(&data) != attachment.sha256 {
+        return Err(AttachmentError::ChecksumMismatch);
+    }
+    std::fs::write(destination, data)?;
+    Ok(())
+}
+
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "txt" | "log" => "text/plain",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+fn read_limited(path: &Path) -> Result<Vec<u8>, AttachmentError> {
+    let mut data = vec![];
+    std::fs::File::open(path)?
+        .take(MAX_ATTACHMENT_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_ATTACHMENT_SIZE {
+        return Err(AttachmentError::TooLarge {
+            size: data.len(),
+            limit: MAX_ATTACHMENT_SIZE,
+        });
+    }
+    Ok(data)
+}
+
+fn same_directory(claimed: &Path, own: &Path) -> bool {
+    match (claimed.canonicalize(), own.canonicalize()) {
+        (Ok(claimed), Ok(own)) => claimed == own,
+        _ => false,
+    }
+}
+
+fn checksum(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn blob_key(id: &str) -> String {
+    format!("attachment:{}", id)
+}
+
+fn human_size(size: usize) -> String {
+    match size {
+        size if size >= 1024 * 1024 => format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0)),
+        size if size >= 1024 => format!("{:.1} KiB", size as f64 / 1024.0),
+        size => format!("{} B", size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tui_chat_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn guesses_content_type_from_extension() {
+        assert_eq!(content_type(Path::new("photo.JPG")), "image/jpeg");
+        assert_eq!(content_type(Path::new("notes.txt")), "text/plain");
+        assert_eq!(
+            content_type(Path::new("archive")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn describes_size_in_binary_units() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn checksums_are_hex_sha256() {
+        assert_eq!(
+            checksum(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn refuses_files_over_the_limit() {
+        let path = temp_path("too_large");
+        std::fs::write(&path, vec![0u8; MAX_ATTACHMENT_SIZE + 1]).unwrap();
+        let read = read_limited(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(read, Err(AttachmentError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn only_the_local_blob_directory_is_trusted() {
+        let own = temp_path("blobs");
+        std::fs::create_dir_all(&own).unwrap();
+        assert!(same_directory(&own, &own.join(".")));
+        assert!(!same_directory(&std::env::temp_dir(), &own));
+        assert!(!same_directory(&own.join("missing"), &own));
+        std::fs::remove_dir(&own).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{connector::read_stream_range, lifecycle::SessionState, session::Session};
+use redis::AsyncCommands;
+use std::{collections::HashMap, hash::BuildHasher};
+
+pub const AUDIT_STREAM: &str = "audit";
+pub const ACTOR_VAR: &str = "TUI_CHAT_ACTOR";
+
+const ACTOR_FIELD: &str = "actor";
+const ACTION_FIELD: &str = "action";
+const SESSION_FIELD: &str = "session";
+const AT_FIELD: &str = "at";
+const BEFORE_FIELD: &str = "before";
+const AFTER_FIELD: &str = "after";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    SessionCreated,
+    TokenIssued,
+    Escalated,
+    Finished,
+    Deleted,
+    StateChanged,
+    TakenOver,
+    HandedBack,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SessionCreated => "session_created",
+            Self::TokenIssued => "token_issued",
+            Self::Escalated => "escalated",
+            Self::Finished => "finished",
+            Self::Deleted => "deleted",
+            Self::StateChanged => "state_changed",
+            Self::TakenOver => "taken_over",
+            Self::HandedBack => "handed_back",
+        }
+    }
+}
+
+impl std::str::FromStr for Action {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        [
+            Self::SessionCreated,
+            Self::TokenIssued,
+            Self::Escalated,
+            Self::Finished,
+            Self::Deleted,
+            Self::StateChanged,
+            Self::TakenOver,
+            Self::HandedBack,
+        ]
+        .into_iter()
+        .find(|action| action.as_str() == value)
+        .ok_or_else(|| format!("unknown action {:?}", value))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub actor: String,
+    pub action: Action,
+    pub session_id: String,
+    pub at: i64,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+impl AuditEntry {
+    fn decode<S: BuildHasher>(id: &str, map: HashMap<String, redis::Value, S>) -> Option<Self> {
+        let field = |name: &str| {
+            map.get(name)
+                .and_then(|value| redis::from_redis_value::<String>(value).ok())
+        };
+        let state = |name: &str| {
+            field(name)
+                .and_then(|state| serde_json::from_str(&state).ok())
+                .unwrap_or_default()
+        };
+        Some(Self {
+            id: id.to_owned(),
+            actor: field(ACTOR_FIELD)?,
+            action: field(ACTION_FIELD)?.parse().ok()?,
+            session_id: field(SESSION_FIELD)?,
+            at: field(AT_FIELD)?.parse().ok()?,
+            before: state(BEFORE_FIELD),
+            after: state(AFTER_FIELD),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    pub session_id: Option<String>,
+    pub actor: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.session_id
+            .as_deref()
+            .is_none_or(|session_id| entry.session_id == session_id)
+            && self
+                .actor
+                .as_deref()
+                .is_none_or(|actor| entry.actor == actor)
+            && self.since.is_none_or(|since| entry.at >= since)
+            && self.until.is_none_or(|until| entry.at <= until)
+    }
+}
+
+/// The user name comes from `TUI_CHAT_ACTOR` or `USER` and is self-reported, not authenticated.
+pub fn local_actor(tool: &str) -> String {
+    std::env::var(ACTOR_VAR)
+        .or_else(|_| std::env::var("USER"))
+        .map(|user| format!("{}@{}", user, tool))
+        .unwrap_or_else(|_| tool.to_owned())
+}
+
+/// Session changes are recorded as `{"state", "operator", "robot"}`; context and keys stay out of the log.
+pub fn session_state(session: &Session) -> serde_json::Value {
+    state_record(session.state, &session.operator, &session.robot)
+}
+
+pub async fn record_transition(
+    con: &mut redis::aio::MultiplexedConnection,
+    actor: &str,
+    action: Action,
+    session_id: &str,
+    previous: SessionState,
+    next: SessionState,
+) -> redis::RedisResult<()> {
+    let (operator, robot) = Session::load(con, session_id)
+        .await
+        .map(|session| (session.operator, session.robot))
+        .unwrap_or_default();
+    let before = state_record(previous, &operator, &robot);
+    let after = state_record(next, &operator, &robot);
+    record(con, actor, action, session_id, &before, &after).await
+}
+
+fn state_record(state: SessionState, operator: &str, robot: &str) -> serde_json::Value {
+    serde_json::json!({ "state": state, "operator": operator, "robot": robot })
+}
+
+pub async fn record(
+    con: &mut redis::aio::MultiplexedConnection,
+    actor: &str,
+    action: Action,
+    session_id: &str,
+    before: &serde_json::Value,
+    after: &serde_json::Value,
+) -> redis::RedisResult<()> {
+    let at = chrono::Utc::now().timestamp_millis().to_string();
+    let before = before.to_string();
+    let after = after.to_string();
+    let items = [
+        (ACTOR_FIELD, actor),
+        (ACTION_FIELD, action.as_str()),
+        (SESSION_FIELD, session_id),
+        (AT_FIELD, &at),
+        (BEFORE_FIELD, &before),
+        (AFTER_FIELD, &after),
+    ];
+    con.xadd(AUDIT_STREAM, "*", &items).await
+}
+
+pub async fn query(
+    con: &mut redis::aio::MultiplexedConnection,
+    query: &AuditQuery,
+) -> redis::RedisResult<Vec<AuditEntry>> {
+    let start = query
+        .since
+        .map(|since| since.max(0).to_string())
+        .unwrap_or_else(|| "-".to_owned());
+    let end = query
+        .until
+        .map(|until| until.max(0).to_string())
+        .unwrap_or_else(|| "+".to_owned());
+    let ids = read_stream_range(con, AUDIT_STREAM, &start, &end).await?;
+    Ok(ids
+        .into_iter()
+        .filter_map(|id| AuditEntry::decode(&id.id, id.map))
+        .filter(|entry| query.matches(entry))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_state_leaves_out_context_and_keys() {
+        let mut session = Session::new("script");
+        session.context = serde_json::json!({ "card": "4111 1111 1111 1111" });
+        session.state = SessionState::OperatorActive;
+        assert_eq!(
+            session_state(&session),
+            serde_json::json!({
+                "state": "operator_active",
+                "operator": "Operator",
+                "robot": "Robot",
+            })
+        );
+    }
+
+    #[test]
+    fn actions_round_trip_through_strings() {
+        for action in [
+            Action::SessionCreated,
+            Action::TokenIssued,
+            Action::StateChanged,
+            Action::HandedBack,
+        ] {
+            assert_eq!(action.as_str().parse::<Action>(), Ok(action));
+        }
+        assert!("dropped".parse::<Action>().is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    role::Role,
+    utils::{from_hex, to_hex},
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+pub const SECRET_VAR: &str = "TUI_CHAT_SECRET";
+pub const ISSUER_KEY_VAR: &str = "TUI_CHAT_ISSUER_KEY";
+pub const TOKEN_VAR: &str = "TUI_CHAT_TOKEN";
+pub const ALL_SESSIONS: &str = "*";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingToken => write!(f, "no token was provided"),
+            Self::InvalidToken => write!(f, "token is not valid for this session"),
+        }
+    }
+}
+
+pub struct Issuer(SigningKey);
+
+impl Issuer {
+    pub fn new(secret: &[u8]) -> Self {
+        Self(SigningKey::from_bytes(&Sha256::digest(secret).into()))
+    }
+
+    pub fn from_env() -> Option<Self> {
+        std::env::var(SECRET_VAR)
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| Self::new(secret.as_bytes()))
+    }
+
+    pub fn public_key(&self) -> IssuerKey {
+        IssuerKey(self.0.verifying_key())
+    }
+
+    pub fn issue(&self, session_id: &str, role: Role, username: &str) -> String {
+        to_hex(&self.0.sign(&claims(session_id, role, username)).to_bytes())
+    }
+
+    pub fn certify(&self, session_id: &str, username: &str, public: &str) -> String {
+        to_hex(
+            &self
+                .0
+                .sign(&certificate(session_id, username, public))
+                .to_bytes(),
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct IssuerKey(VerifyingKey);
+
+impl IssuerKey {
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let bytes: [u8; 32] = from_hex(hex)?.try_into().ok()?;
+        VerifyingKey::from_bytes(&bytes).ok().map(Self)
+    }
+
+    pub fn from_env() -> Option<Self> {
+        std::env::var(ISSUER_KEY_VAR)
+            .ok()
+            .and_then(|hex| Self::from_hex(&hex))
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(self.0.as_bytes())
+    }
+
+    pub fn verify(
+        &self,
+        token: Option<&str>,
+        session_id: &str,
+        role: Role,
+        username: &str,
+    ) -> Result<(), AuthError> {
+        let token = token.ok_or(AuthError::MissingToken)?;
+        let signature = from_hex(token)
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or(AuthError::InvalidToken)?;
+        let sessions: &[&str] = match role {
+            Role::Supervisor => &[session_id, ALL_SESSIONS],
+            _ => &[session_id],
+        };
+        sessions
+            .iter()
+            .any(|session_id| {
+                self.0
+                    .verify(&claims(session_id, role, username), &signature)
+                    .is_ok()
+            })
+            .then_some(())
+            .ok_or(AuthError::InvalidToken)
+    }
+
+    pub fn check_certificate(
+        &self,
+        signature: &str,
+        session_id: &str,
+        username: &str,
+        public: &str,
+    ) -> bool {
+        from_hex(signature)
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .is_some_and(|signature| {
+                self.0
+                    .verify(&certificate(session_id, username, public), &signature)
+                    .is_ok()
+            })
+    }
+}
+
+pub fn token_from_env() -> Option<String> {
+    std::env::var(TOKEN_VAR)
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+fn claims(session_id: &str, role: Role, username: &str) -> Vec<u8> {
+    encode(&["token", session_id, role.as_str(), username])
+}
+
+fn certificate(session_id: &str, username: &str, public: &str) -> Vec<u8> {
+    encode(&["key", session_id, username, public])
+}
+
+fn encode(parts: &[&str]) -> Vec<u8> {
+    let mut encoded = vec![];
+    for part in parts {
+        encoded.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        encoded.extend_from_slice(part.as_bytes());
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issued_token_verifies_for_its_claims_only() {
+        let issuer = Issuer::new(b"secret");
+        let key = issuer.public_key();
+        let token = issuer.issue("session", Role::Customer, "Customer");
+        assert_eq!(
+            key.verify(Some(&token), "session", Role::Customer, "Customer"),
+            Ok(())
+        );
+        assert_eq!(
+            key.verify(Some(&token), "other", Role::Customer, "Customer"),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            key.verify(Some(&token), "session", Role::Operator, "Customer"),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            key.verify(Some(&token), "session", Role::Customer, "Operator"),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            key.verify(None, "session", Role::Customer, "Customer"),
+            Err(AuthError::MissingToken)
+        );
+    }
+
+    #[test]
+    fn token_from_another_issuer_is_rejected() {
+        let token = Issuer::new(b"other").issue("session", Role::Customer, "Customer");
+        let key = Issuer::new(b"secret").public_key();
+        assert_eq!(
+            key.verify(Some(&token), "session", Role::Customer, "Customer"),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            key.verify(Some("zz"), "session", Role::Customer, "Customer"),
+            Err(AuthError::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn certificate_binds_key_to_session_and_participant() {
+        let issuer = Issuer::new(b"secret");
+        let key = issuer.public_key();
+        let certificate = issuer.certify("session", "Customer", "abcd");
+        assert!(key.check_certificate(&certificate, "session", "Customer", "abcd"));
+        assert!(!key.check_certificate(&certificate, "session", "Operator", "abcd"));
+        assert!(!key.check_certificate(&certificate, "session", "Customer", "abce"));
+        assert!(!key.check_certificate(&certificate, "other", "Customer", "abcd"));
+    }
+
+    #[test]
+    fn wildcard_token_is_accepted_for_supervisors_only() {
+        let issuer = Issuer::new(b"secret");
+        let key = IssuerKey::from_hex(&issuer.public_key().to_hex()).unwrap();
+        let token = issuer.issue(ALL_SESSIONS, Role::Supervisor, "Operator");
+        assert_eq!(
+            key.verify(Some(&token), "session", Role::Supervisor, "Operator"),
+            Ok(())
+        );
+        let token = issuer.issue(ALL_SESSIONS, Role::Operator, "Operator");
+        assert_eq!(
+            key.verify(Some(&token), "session", Role::Operator, "Operator"),
+            Err(AuthError::InvalidToken)
+        );
+    }
+}
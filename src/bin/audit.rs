@@ -0,0 +1,79 @@
+use tui_chat::{
+    audit::{self, AuditEntry, AuditQuery},
+    transcript,
+};
+
+const USAGE: &str = "\nUsage:\n\taudit [--session SESSION_ID] [--actor ACTOR] [--since TIME] [--until TIME] [--format jsonl|text]\n\nTIME is either milliseconds since the epoch or an RFC 3339 timestamp.\n";
+
+#[derive(Clone, Copy)]
+enum Format {
+    JsonLines,
+    Text,
+}
+
+#[tokio::main]
+async fn main() {
+    let (query, format) = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    let entries = match audit::query(&mut con, &query).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read the audit stream: {:?}", e);
+            return;
+        }
+    };
+    for entry in &entries {
+        match format {
+            Format::JsonLines => match serde_json::to_string(entry) {
+                Ok(line) => println!("{}", line),
+                Err(e) => eprintln!("Failed to encode {}: {}", entry.id, e),
+            },
+            Format::Text => println!("{}", describe(entry)),
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(AuditQuery, Format), String> {
+    let mut query = AuditQuery::default();
+    let mut format = Format::JsonLines;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--session" => query.session_id = Some(value()?),
+            "--actor" => query.actor = Some(value()?),
+            "--since" => query.since = Some(transcript::parse_time(&value()?)?),
+            "--until" => query.until = Some(transcript::parse_time(&value()?)?),
+            "--format" => {
+                format = match value()?.as_str() {
+                    "jsonl" => Format::JsonLines,
+                    "text" => Format::Text,
+                    other => return Err(format!("unknown format {:?}", other)),
+                }
+            }
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok((query, format))
+}
+
+fn describe(entry: &AuditEntry) -> String {
+    let at = chrono::DateTime::from_timestamp_millis(entry.at)
+        .map(|at| at.to_rfc3339())
+        .unwrap_or_else(|| entry.at.to_string());
+    format!(
+        "{} {} {} {}\n\tbefore: {}\n\tafter:  {}",
+        at,
+        entry.actor,
+        entry.action.as_str(),
+        entry.session_id,
+        entry.before,
+        entry.after
+    )
+}
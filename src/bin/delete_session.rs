@@ -0,0 +1,55 @@
+use redis::{AsyncCommands, JsonAsyncCommands};
+use tui_chat::{
+    audit::{self, Action},
+    notes,
+    session::Session,
+};
+
+const USAGE: &str = "\nUsage:\n\tdelete_session SESSION_ID [--with-chat]\n\n--with-chat also deletes the chat and notes streams.\n";
+
+#[tokio::main]
+async fn main() {
+    let (flags, args): (Vec<_>, Vec<_>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let Some(session_id) = args.first() else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let with_chat = flags.iter().any(|flag| flag == "--with-chat");
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    let session: redis::RedisResult<String> = con.json_get(session_id, "$").await;
+    let Some(session) = session
+        .ok()
+        .and_then(|s| serde_json::from_str::<Vec<Session>>(&s).ok())
+        .and_then(|sessions| sessions.into_iter().next())
+    else {
+        eprintln!("Session {:?} not found.", session_id);
+        return;
+    };
+    let deleted: redis::RedisResult<()> = con.del(session_id).await;
+    if let Err(e) = deleted {
+        eprintln!("Failed to delete session {:?}: {:?}", session_id, e);
+        return;
+    }
+    if with_chat {
+        let keys = [session.chat_id.clone(), notes::stream_key(&session.chat_id)];
+        let deleted: redis::RedisResult<()> = con.del(&keys).await;
+        if let Err(e) = deleted {
+            eprintln!("Failed to delete chat {:?}: {:?}", session.chat_id, e);
+        }
+    }
+    eprintln!("Deleted session: {:?}", session_id);
+    let recorded = audit::record(
+        &mut con,
+        &audit::local_actor("delete_session"),
+        Action::Deleted,
+        session_id,
+        &audit::session_state(&session),
+        &serde_json::json!({ "chat_deleted": with_chat }),
+    )
+    .await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to audit session deletion: {:?}", e);
+    }
+}
@@ -0,0 +1,88 @@
+use tui_chat::{
+    session::Session,
+    signing::Keyring,
+    transcript::{self, ExportOptions, Format},
+};
+
+const USAGE: &str = "\nUsage:\n\texport CHAT_ID [--format jsonl|markdown|text] [--since TIME] [--until TIME] [--redact FIELD,...] [--session SESSION_ID] [--output FILE]\n\nTIME is either milliseconds since the epoch or an RFC 3339 timestamp.\nWith --session, entries are checked against the participants' signing keys and forged or unsigned edits and deletes are skipped.\n";
+
+struct Args {
+    chat_id: String,
+    format: Format,
+    options: ExportOptions,
+    session_id: Option<String>,
+    output: Option<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    let (roles, keyring) = match args.session_id.as_deref() {
+        Some(session_id) => match Session::load(&mut con, session_id).await {
+            Some(session) => (
+                transcript::roles_from_session(&session),
+                Keyring::from_session(session_id, &session),
+            ),
+            None => Default::default(),
+        },
+        None => (
+            transcript::roles_from_session(&Session::new("")),
+            Keyring::default(),
+        ),
+    };
+    let read =
+        transcript::read_transcript(&mut con, &args.chat_id, &roles, &keyring, &args.options).await;
+    let entries = match read {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read transcript: {}", e);
+            return;
+        }
+    };
+    let exported = transcript::export(&args.chat_id, &entries, args.format);
+    match args.output {
+        Some(path) => match std::fs::write(&path, exported) {
+            Ok(()) => eprintln!("Exported {} entries to {:?}", entries.len(), path),
+            Err(e) => eprintln!("Failed to write {:?}: {}", path, e),
+        },
+        None => print!("{}", exported),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut chat_id = None;
+    let mut format = Format::JsonLines;
+    let mut options = ExportOptions::default();
+    let mut session_id = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--format" => format = value()?.parse()?,
+            "--since" => options.since = Some(transcript::parse_time(&value()?)?),
+            "--until" => options.until = Some(transcript::parse_time(&value()?)?),
+            "--redact" => options
+                .redact
+                .extend(value()?.split(',').map(|field| field.trim().to_owned())),
+            "--session" => session_id = Some(value()?),
+            "--output" => output = Some(value()?),
+            _ if chat_id.is_none() && !arg.starts_with("--") => chat_id = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(Args {
+        chat_id: chat_id.ok_or("CHAT_ID is required")?,
+        format,
+        options,
+        session_id,
+        output,
+    })
+}
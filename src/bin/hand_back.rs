@@ -0,0 +1,44 @@
+use tui_chat::{
+    audit, handoff,
+    signing::SigningKey,
+    system::{self, SystemEvent},
+};
+
+const USAGE: &str = "\nUsage:\n\thand_back SESSION_ID [CONTEXT_JSON]\n\nCONTEXT_JSON is an optional object merged into the robot context.\nThe hand-back is announced in the chat as the operator, signed with TUI_CHAT_SIGNING_KEY when set.\n";
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(session_id) = args.next() else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let changes = match args
+        .next()
+        .map(|raw| serde_json::from_str::<serde_json::Value>(&raw))
+    {
+        None => None,
+        Some(Ok(changes)) if changes.is_object() => Some(changes),
+        _ => {
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    let actor = audit::local_actor("hand_back");
+    match handoff::hand_back(&mut con, &session_id, &actor, changes.as_ref()).await {
+        Ok(previous) => {
+            eprintln!(
+                "Session {:?}: {} -> bot_active",
+                session_id,
+                previous.as_str()
+            );
+            let key = SigningKey::from_env();
+            let event = SystemEvent::HandedBack;
+            if !system::announce_as_operator(&mut con, &session_id, key.as_ref(), event).await {
+                eprintln!("Failed to announce the hand-back.");
+            }
+        }
+        Err(e) => eprintln!("Session {:?}: {}", session_id, e),
+    }
+}
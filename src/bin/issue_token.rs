@@ -0,0 +1,71 @@
+use redis::JsonAsyncCommands;
+use tui_chat::{
+    audit::{self, Action},
+    auth::{Issuer, ALL_SESSIONS},
+    role::Role,
+    session::Session,
+};
+
+const USAGE: &str = "\nUsage:\n\tissue_token SESSION_ID ROLE\n\tissue_token --all-sessions USERNAME\n\nThe token is signed with TUI_CHAT_SECRET and checked with TUI_CHAT_ISSUER_KEY.\n--all-sessions issues a supervisor token valid for every session.\n";
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(session_id), Some(role)) = (args.next(), args.next()) else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let Some(issuer) = Issuer::from_env() else {
+        eprintln!("TUI_CHAT_SECRET is not set.");
+        return;
+    };
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    if session_id == "--all-sessions" {
+        let username = role;
+        println!(
+            "{}",
+            issuer.issue(ALL_SESSIONS, Role::Supervisor, &username)
+        );
+        audit_issue(&mut con, ALL_SESSIONS, Role::Supervisor, &username).await;
+        return;
+    }
+    let role = match role.parse::<Role>() {
+        Ok(role) => role,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let session: redis::RedisResult<String> = con.json_get(&session_id, "$").await;
+    let Some(session) = session
+        .ok()
+        .and_then(|s| serde_json::from_str::<Vec<Session>>(&s).ok())
+        .and_then(|sessions| sessions.into_iter().next())
+    else {
+        eprintln!("Session {:?} not found.", session_id);
+        return;
+    };
+    let username = session.username_for(role);
+    println!("{}", issuer.issue(&session_id, role, username));
+    audit_issue(&mut con, &session_id, role, username).await;
+}
+
+async fn audit_issue(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+    role: Role,
+    username: &str,
+) {
+    let recorded = audit::record(
+        con,
+        &audit::local_actor("issue_token"),
+        Action::TokenIssued,
+        session_id,
+        &serde_json::Value::Null,
+        &serde_json::json!({ "role": role.as_str(), "username": username }),
+    )
+    .await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to audit token issue: {:?}", e);
+    }
+}
@@ -0,0 +1,108 @@
+use redis::JsonAsyncCommands;
+use tui_chat::{
+    signing::SigningKey,
+    transcript::{self, ReplayMode},
+};
+
+const USAGE: &str = "\nUsage:\n\treplay FILE [CHAT_ID] [--bulk | --speed FACTOR] [--as NAME]\n\nFILE is a transcript exported as JSON lines.\nWithout CHAT_ID a new session is created for the replay.\nEntries are posted as NAME (default Replay) with the original author in @replay, signed with TUI_CHAT_SIGNING_KEY when set.\n";
+const DEFAULT_AUTHOR: &str = "Replay";
+
+struct Args {
+    path: String,
+    chat_id: Option<String>,
+    mode: ReplayMode,
+    author: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+    let entries = match std::fs::read_to_string(&args.path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| transcript::import(&content))
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to import {:?}: {}", args.path, e);
+            return;
+        }
+    };
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    let chat_id = match args.chat_id {
+        Some(chat_id) => chat_id,
+        None => {
+            let session = tui_chat::session::Session::new("replay");
+            let session_id = format!("{}", uuid::Uuid::new_v4());
+            let created: redis::RedisResult<()> = con.json_set(&session_id, "$", &session).await;
+            if let Err(e) = created {
+                eprintln!("Failed to create session {:?}: {}", session_id, e);
+                return;
+            }
+            eprintln!("Created session: {:?}", session_id);
+            session.chat_id
+        }
+    };
+    eprintln!(
+        "Replaying {} entries into chat {:?}",
+        entries.len(),
+        chat_id
+    );
+    let key = SigningKey::from_env();
+    let replayed = transcript::replay(
+        &mut con,
+        &chat_id,
+        &args.author,
+        key.as_ref(),
+        &entries,
+        args.mode,
+    )
+    .await;
+    match replayed {
+        Ok(replayed) => eprintln!("Replayed {} entries", replayed),
+        Err((replayed, e)) => eprintln!("Replay stopped after {} entries: {}", replayed, e),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut positional = vec![];
+    let mut mode = ReplayMode::Timed { speed: 1.0 };
+    let mut author = DEFAULT_AUTHOR.to_owned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bulk" => mode = ReplayMode::Bulk,
+            "--as" => author = args.next().ok_or("--as needs a value")?,
+            "--speed" => {
+                let speed = args
+                    .next()
+                    .ok_or("--speed needs a value")?
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid speed: {}", e))?;
+                if speed <= 0.0 {
+                    return Err("speed must be positive".to_owned());
+                }
+                mode = ReplayMode::Timed { speed };
+            }
+            _ if !arg.starts_with("--") => positional.push(arg),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let path = positional.next().ok_or("FILE is required")?;
+    let chat_id = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {:?}", extra));
+    }
+    Ok(Args {
+        path,
+        chat_id,
+        mode,
+        author,
+    })
+}
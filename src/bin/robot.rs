@@ -1,126 +1,32 @@
-use redis::JsonAsyncCommands;
-use tui_chat::interpret::Command;
+use tui_chat::{
+    bot::{self, ScriptServer, ServeOptions},
+    metrics,
+    middleware::{Chain, PiiMask, SizeLimit},
+    rate_limit::Limits,
+    signing::SigningKey,
+};
+
+const E2E_FLAG: &str = "--e2e";
+const MASK_PII_FLAG: &str = "--mask-pii";
+const METRICS_ADDR_FLAG: &str = "--metrics-addr=";
 
 #[tokio::main]
 async fn main() {
     let session_id = std::env::args().nth(1).unwrap();
-    serve(&session_id).await;
-}
-
-async fn serve(session_id: &str) {
-    let mut async_connection_to_redis = tui_chat::connector::create_async_redis_connection().await;
-    let con = &mut async_connection_to_redis;
-    let session: redis::RedisResult<_> = con
-        .json_get(session_id, "$")
-        .await
-        .map(|s: String| serde_json::from_str::<Vec<tui_chat::session::Session>>(&s).unwrap());
-    let Ok(mut sessions) = session else {
-        return;
-    };
-    let session = sessions.first_mut().unwrap();
-    let chat_client = reqwest::Client::new();
-
-    let mut keep_going = true;
-    while keep_going {
-        eprintln!("Send: {:#?}", session.context);
-        match interpret(&chat_client, session).await {
-            Ok(resp) if resp.status().is_success() => match on_success(resp, con, session).await {
-                Some(proceed) => keep_going = proceed,
-                None => break,
-            },
-            Err(_e) => todo!(),
-            Ok(bad_resp) => {
-                eprintln!("ERROR: {:#?}", bad_resp.text().await);
-                break;
-            }
-        };
-        session.update_to_redis(con, session_id).await;
+    let mut inbound = Chain::default().with(SizeLimit::new(Limits::default().max_message_bytes));
+    if std::env::args().skip(2).any(|arg| arg == MASK_PII_FLAG) {
+        inbound = inbound.with(PiiMask);
     }
-    eprintln!("Final: {:#?}", session.context);
-}
-
-fn interpret(
-    chat_client: &reqwest::Client,
-    session: &tui_chat::session::Session,
-) -> impl std::future::Future<Output = reqwest::Result<reqwest::Response>> {
-    chat_client
-        .post(format!(
-            "http://127.0.0.1:8000/api/v1/scripts/{}",
-            session.script
-        ))
-        .json(&session.context)
-        .send()
-}
-
-async fn on_success(
-    resp: reqwest::Response,
-    con: &mut redis::aio::MultiplexedConnection,
-    session: &mut tui_chat::session::Session,
-) -> Option<bool> {
-    match resp.json::<serde_json::Value>().await {
-        Ok(mut interpreted) => {
-            eprintln!("Received: {:#?}", interpreted);
-            session
-                .send_user_output_to_redis(con, interpreted["user_output"].take())
-                .await;
-
-            session.context["context"] = interpreted["context"].take();
-            match Command::from(interpreted["command"].as_str().unwrap()) {
-                Command::Wait => {
-                    wait_for_user_input(con, session).await;
-                    Some(true)
-                }
-                Command::Finish => {
-                    session.context = serde_json::json!({});
-                    Some(false)
-                }
-                Command::Pause => Some(true),
-                Command::Operator => {
-                    eprintln!(
-                        "Need operator in chat {:?}. {:?}",
-                        session.chat_id,
-                        session.context["operator_message"].as_str()
-                    );
-                    Some(false)
-                }
-                Command::Noop => {
-                    eprint!("NOOP after command: {:?}.", interpreted["command"]);
-                    None
-                }
-            }
-        }
-        Err(e) => {
-            eprintln!("Failed to receive JSON-response: {:?}", e);
-            None
-        }
-    }
-}
-
-async fn wait_for_user_input(
-    con: &mut redis::aio::MultiplexedConnection,
-    session: &mut tui_chat::session::Session,
-) {
-    let mut user_input = vec![];
-
-    while user_input.is_empty() {
-        match tui_chat::connector::read_from_stream(con, &session.chat_id, &session.stream_id).await
-        {
-            Ok(keys) => {
-                for key in keys {
-                    for id in key.ids {
-                        session.stream_id = id.id;
-                        for (source, msg) in id.map {
-                            if source == session.username {
-                                if let Ok(text) = redis::from_owned_redis_value(msg) {
-                                    user_input.push(serde_json::Value::String(text));
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            Err(_) => todo!(),
-        }
-    }
-    session.context["user_input"] = serde_json::Value::Array(user_input);
+    let options = ServeOptions {
+        e2e: std::env::args().skip(2).any(|arg| arg == E2E_FLAG),
+        signing_key: SigningKey::from_env(),
+        inbound,
+        idle_timeout: bot::idle_timeout_from_env(),
+    };
+    let metrics_addr = std::env::args()
+        .skip(2)
+        .find_map(|arg| arg.strip_prefix(METRICS_ADDR_FLAG).map(ToOwned::to_owned))
+        .unwrap_or_else(metrics::addr_from_env);
+    tokio::spawn(metrics::serve(metrics_addr));
+    bot::serve(&ScriptServer::default(), &session_id, options).await;
 }
@@ -0,0 +1,56 @@
+use tui_chat::{
+    audit::{self, Action},
+    lifecycle::{self, SessionState},
+    signing::SigningKey,
+    system::{self, SystemEvent},
+};
+
+const USAGE: &str = "\nUsage:\n\tset_state SESSION_ID STATE\n\nSTATE is one of created, bot_active, waiting_operator, operator_active, paused, finished, abandoned.\nThe change is announced in the chat as the operator, signed with TUI_CHAT_SIGNING_KEY when set.\n";
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(session_id), Some(state)) = (args.next(), args.next()) else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let next = match state.parse::<SessionState>() {
+        Ok(next) => next,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut con = tui_chat::connector::create_async_redis_connection().await;
+    let previous = match lifecycle::transition(&mut con, &session_id, next).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            eprintln!("Session {:?}: {}", session_id, e);
+            return;
+        }
+    };
+    eprintln!(
+        "Session {:?}: {} -> {}",
+        session_id,
+        previous.as_str(),
+        next.as_str()
+    );
+    let recorded = audit::record_transition(
+        &mut con,
+        &audit::local_actor("set_state"),
+        Action::StateChanged,
+        &session_id,
+        previous,
+        next,
+    )
+    .await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to audit the state change: {:?}", e);
+    }
+    if let Some(event) = SystemEvent::for_state(next).filter(|_| previous != next) {
+        let key = SigningKey::from_env();
+        if !system::announce_as_operator(&mut con, &session_id, key.as_ref(), event).await {
+            eprintln!("Failed to announce the state change.");
+        }
+    }
+}
@@ -1,10 +1,61 @@
 use redis::JsonAsyncCommands;
+use tui_chat::{
+    audit::{self, Action},
+    auth::Issuer,
+    role::Role,
+    signing::{ParticipantKey, SigningKey},
+};
 
 #[tokio::main]
 async fn main() {
     let mut con = tui_chat::connector::create_async_redis_connection().await;
-    let session = tui_chat::session::Session::new(&std::env::args().nth(1).unwrap());
+    let (flags, args): (Vec<_>, Vec<_>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let mut session = tui_chat::session::Session::new(&args[0]);
+    session.locale = args.get(1).cloned();
+    session.encrypted = flags.iter().any(|flag| flag == "--encrypt");
     let session_id = format!("{}", uuid::Uuid::new_v4());
+    let issuer = Issuer::from_env();
+    let mut signing_keys = vec![];
+    for role in [Role::Customer, Role::Robot, Role::Operator] {
+        let username = session.username_for(role).to_owned();
+        let key = SigningKey::generate();
+        let public = key.public_hex();
+        let certificate = issuer
+            .as_ref()
+            .map(|issuer| issuer.certify(&session_id, &username, &public));
+        session.keys.insert(
+            username.clone(),
+            ParticipantKey {
+                public,
+                certificate,
+            },
+        );
+        signing_keys.push((username, key));
+    }
     let _: () = con.json_set(&session_id, "$", &session).await.unwrap();
     eprintln!("Created session: {:?}", session_id);
+    let recorded = audit::record(
+        &mut con,
+        &audit::local_actor("start_session"),
+        Action::SessionCreated,
+        &session_id,
+        &serde_json::Value::Null,
+        &audit::session_state(&session),
+    )
+    .await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to audit session creation: {:?}", e);
+    }
+    for (username, key) in signing_keys {
+        eprintln!("Signing key for {}: {}", username, key.to_hex());
+    }
+    if let Some(issuer) = issuer {
+        eprintln!("Issuer key: {}", issuer.public_key().to_hex());
+        for role in [Role::Customer, Role::Operator, Role::Supervisor] {
+            let token = issuer.issue(&session_id, role, session.username_for(role));
+            eprintln!("Token for {}: {}", role.as_str(), token);
+        }
+    }
 }
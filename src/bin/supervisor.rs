@@ -0,0 +1,39 @@
+use tui_chat::{
+    app::{supervisor::Supervisor, App},
+    auth,
+    locale::{self, Locale},
+    middleware::{Chain, LinkDetector, Pipeline, ProfanityFilter, SizeLimit},
+    rate_limit::Limits,
+    role::Role,
+};
+
+fn main() {
+    let session_ids: Vec<String> = std::env::args().skip(1).collect();
+    if session_ids.iter().any(|arg| arg.starts_with("--")) {
+        eprintln!("\nUsage:\n\tsupervisor [SESSION_ID...]\n");
+        eprintln!("Without SESSION_ID every session in Redis is monitored.");
+        eprintln!("Requires TUI_CHAT_ISSUER_KEY and a supervisor token in TUI_CHAT_TOKEN.");
+        eprintln!("Use `issue_token --all-sessions USERNAME` for a token valid in every session.");
+        return;
+    }
+    loop {
+        locale::set_locale(Locale::from_env());
+        let token = auth::token_from_env();
+        let Some(chat) = Supervisor::new().go(&session_ids, token.as_deref()) else {
+            return;
+        };
+        let max_bytes = Limits::default().max_message_bytes;
+        let pipeline = Pipeline {
+            outbound: Chain::default(),
+            inbound: Chain::default()
+                .with(SizeLimit::new(max_bytes))
+                .with(ProfanityFilter::default())
+                .with(LinkDetector),
+        };
+        App::new().with_pipeline(pipeline).with_read_only(true).go(
+            &chat.session_id,
+            Role::Supervisor,
+            token,
+        );
+    }
+}
@@ -1,9 +1,60 @@
+use tui_chat::{
+    auth, metrics,
+    middleware::{Chain, LinkDetector, PiiMask, Pipeline, ProfanityFilter, SizeLimit},
+    rate_limit::Limits,
+    role::Role,
+};
+
+const READ_ONLY_FLAG: &str = "--read-only";
+
 fn main() {
-    if let Some(session_id) = std::env::args().nth(1) {
-        let app = tui_chat::app::App::new();
-        app.go(&session_id);
-    } else {
-        eprintln!("\nUsage:\n\twidget SESSION_ID\n");
+    let (flags, args): (Vec<_>, Vec<_>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let mut args = args.into_iter();
+    let Some(session_id) = args.next() else {
+        eprintln!(
+            "\nUsage:\n\twidget SESSION_ID [ROLE] [{}]\n",
+            READ_ONLY_FLAG
+        );
+        eprintln!("When TUI_CHAT_ISSUER_KEY is set, pass the participant token in TUI_CHAT_TOKEN.");
+        eprintln!(
+            "Pass the participant signing key printed by start_session in TUI_CHAT_SIGNING_KEY."
+        );
+        eprintln!("Set TUI_CHAT_PII_MASK=1 to mask e-mails and phone numbers in sent messages.");
+        eprintln!("Set TUI_CHAT_METRICS_FILE to dump metrics to a file periodically.");
+        eprintln!(
+            "{} hides the composer and never posts to the chat.",
+            READ_ONLY_FLAG
+        );
         eprintln!("Please start over with SESSION_ID");
+        return;
+    };
+    let role = match args.next().map(|role| role.parse::<Role>()) {
+        Some(Ok(role)) => role,
+        Some(Err(e)) => {
+            eprintln!("{}. Expected customer, operator or supervisor.", e);
+            return;
+        }
+        None => Role::Customer,
+    };
+    let max_bytes = Limits::default().max_message_bytes;
+    let mut outbound = Chain::default().with(SizeLimit::new(max_bytes));
+    if PiiMask::enabled_from_env() {
+        outbound = outbound.with(PiiMask);
     }
+    let pipeline = Pipeline {
+        outbound,
+        inbound: Chain::default()
+            .with(SizeLimit::new(max_bytes))
+            .with(ProfanityFilter::default())
+            .with(LinkDetector),
+    };
+    let mut app = tui_chat::app::App::new()
+        .with_pipeline(pipeline)
+        .with_read_only(flags.iter().any(|flag| flag == READ_ONLY_FLAG));
+    if let Some(path) = metrics::file_from_env() {
+        app = app.with_metrics_file(path);
+    }
+    app.go(&session_id, role, auth::token_from_env());
 }
@@ -0,0 +1,694 @@
+use crate::{
+    audit::{self, Action},
+    connector::{create_async_redis_connection, read_from_stream, read_stream_range},
+    e2e::{self, ChatKey, KeyPair, KEY_TIMEOUT},
+    handoff,
+    interpret::Command,
+    lifecycle::{self, SessionState},
+    message::{Body, Kind, Message},
+    metrics,
+    middleware::Chain,
+    presence,
+    role::Role,
+    session::Session,
+    signing::{Keyring, SigningKey, Trust, SIGNING_KEY_VAR},
+    system::{self, SystemEvent},
+    typing,
+};
+use futures_util::future::BoxFuture;
+use redis::{streams::StreamRangeReply, AsyncCommands};
+use std::time::Duration;
+
+pub const SCRIPT_SERVER_URL: &str = "http://127.0.0.1:8000";
+pub const IDLE_TIMEOUT_VAR: &str = "TUI_CHAT_IDLE_TIMEOUT";
+
+#[derive(Debug)]
+pub struct Turn {
+    pub user_output: serde_json::Value,
+    pub context: serde_json::Value,
+    pub command: Command,
+}
+
+#[derive(Debug)]
+pub enum BotError {
+    Http(reqwest::Error),
+    Status { status: u16, body: String },
+    Unhandled,
+}
+
+impl std::fmt::Display for BotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "{}", e),
+            Self::Status { status, body } => write!(f, "status {}: {}", status, body),
+            Self::Unhandled => write!(f, "no handler produced a response"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for BotError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+pub trait Bot: Send + Sync {
+    fn respond<'a>(&'a self, session: &'a Session) -> BoxFuture<'a, Result<Turn, BotError>>;
+}
+
+pub trait Handler: Send + Sync {
+    fn handle(&self, context: &serde_json::Value, user_input: &[String]) -> Option<Turn>;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&serde_json::Value, &[String]) -> Option<Turn> + Send + Sync,
+{
+    fn handle(&self, context: &serde_json::Value, user_input: &[String]) -> Option<Turn> {
+        self(context, user_input)
+    }
+}
+
+pub struct ScriptServer {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl ScriptServer {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+impl Default for ScriptServer {
+    fn default() -> Self {
+        Self::new(SCRIPT_SERVER_URL)
+    }
+}
+
+impl Bot for ScriptServer {
+    fn respond<'a>(&'a self, session: &'a Session) -> BoxFuture<'a, Result<Turn, BotError>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(format!(
+                    "{}/api/v1/scripts/{}",
+                    self.base_url, session.script
+                ))
+                .json(&session.context)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(BotError::Status {
+                    status: response.status().as_u16(),
+                    body: response.text().await.unwrap_or_default(),
+                });
+            }
+            let mut interpreted = response.json::<serde_json::Value>().await?;
+            eprintln!("Received: {:#?}", interpreted);
+            Ok(Turn {
+                user_output: interpreted["user_output"].take(),
+                context: interpreted["context"].take(),
+                command: Command::from(interpreted["command"].as_str().unwrap_or_default()),
+            })
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Composite {
+    handlers: Vec<Box<dyn Handler>>,
+    fallback: Option<Box<dyn Bot>>,
+}
+
+impl Composite {
+    pub fn with(mut self, handler: impl Handler + 'static) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    pub fn fallback(mut self, bot: impl Bot + 'static) -> Self {
+        self.fallback = Some(Box::new(bot));
+        self
+    }
+}
+
+impl Bot for Composite {
+    fn respond<'a>(&'a self, session: &'a Session) -> BoxFuture<'a, Result<Turn, BotError>> {
+        Box::pin(async move {
+            let user_input = session.user_input();
+            let context = &session.context["context"];
+            if let Some(turn) = self
+                .handlers
+                .iter()
+                .find_map(|handler| handler.handle(context, &user_input))
+            {
+                return Ok(turn);
+            }
+            match self.fallback.as_ref() {
+                Some(bot) => bot.respond(session).await,
+                None => Err(BotError::Unhandled),
+            }
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct ServeOptions {
+    pub e2e: bool,
+    pub signing_key: Option<SigningKey>,
+    pub inbound: Chain,
+    pub idle_timeout: Option<Duration>,
+}
+
+struct Guards {
+    key: Option<SigningKey>,
+    keyring: Keyring,
+    chat_key: Option<ChatKey>,
+    inbound: Chain,
+    idle_timeout: Option<Duration>,
+}
+
+pub fn idle_timeout_from_env() -> Option<Duration> {
+    std::env::var(IDLE_TIMEOUT_VAR)
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs)
+}
+
+pub async fn serve(bot: &dyn Bot, session_id: &str, options: ServeOptions) {
+    let mut async_connection_to_redis = create_async_redis_connection().await;
+    let con = &mut async_connection_to_redis;
+    let Some(mut stored) = Session::load(con, session_id).await else {
+        return;
+    };
+    let session = &mut stored;
+    if !session.state.bot_may_run() {
+        eprintln!(
+            "Session {} is {}, the robot will not run.",
+            session_id,
+            session.state.as_str()
+        );
+        return;
+    }
+    let keyring = Keyring::from_session(session_id, session);
+    let mut exchange = None;
+    let chat_key = if session.encrypted {
+        if !options.e2e {
+            eprintln!(
+                "Session {} is end-to-end encrypted. The robot needs an explicit opt-in to hold a key.",
+                session_id
+            );
+            return;
+        }
+        let Some(identity) = options.signing_key.clone() else {
+            eprintln!(
+                "Session {} is end-to-end encrypted. The robot needs {} to publish its key.",
+                session_id, SIGNING_KEY_VAR
+            );
+            return;
+        };
+        let keys = match KeyPair::load_or_create(&session.chat_id, &session.robot) {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("Failed to load the robot key: {}", e);
+                return;
+            }
+        };
+        let (key_tx, key_rx) = tokio::sync::watch::channel(None);
+        exchange = Some(tokio::spawn(e2e::key_exchange(
+            session.chat_id.clone(),
+            session.robot.clone(),
+            identity,
+            keyring.clone(),
+            keys,
+            key_tx,
+        )));
+        match e2e::wait_for_key(&mut Some(key_rx), KEY_TIMEOUT).await {
+            Ok(chat_key) => chat_key,
+            Err(_) => {
+                eprintln!("Timed out waiting for the key of session {}.", session_id);
+                if let Some(exchange) = exchange {
+                    exchange.abort();
+                }
+                return;
+            }
+        }
+    } else {
+        None
+    };
+    let guards = Guards {
+        key: options.signing_key,
+        keyring,
+        chat_key,
+        inbound: options.inbound,
+        idle_timeout: options.idle_timeout,
+    };
+
+    if !change_state(con, session, session_id, SessionState::BotActive).await {
+        return;
+    }
+    metrics::gauge(&metrics::ACTIVE_SESSIONS, 1);
+    presence::join(con, &session.chat_id, &session.robot, Role::Robot).await;
+    let heartbeat = tokio::spawn(presence::keep_alive(
+        session.chat_id.clone(),
+        session.robot.clone(),
+        Role::Robot,
+    ));
+    announce(con, session, &guards, SystemEvent::BotJoined).await;
+
+    loop {
+        match lifecycle::load(con, session_id).await {
+            Ok(SessionState::BotActive) => {}
+            Ok(SessionState::WaitingOperator | SessionState::OperatorActive) => {
+                if !resume_after_operator(con, session, session_id, &guards).await {
+                    break;
+                }
+            }
+            Ok(state) => {
+                eprintln!("Session {} is {}, stopping.", session_id, state.as_str());
+                break;
+            }
+            Err(e) => eprintln!("Failed to load the session state: {}", e),
+        }
+        eprintln!("Send: {:#?}", session.context);
+        let proceed = match respond_while_typing(bot, con, session).await {
+            Ok(turn) => on_turn(turn, con, session, session_id, &guards).await,
+            Err(e) => {
+                eprintln!("ERROR: {}", e);
+                None
+            }
+        };
+        let Some(proceed) = proceed else {
+            break;
+        };
+        session.save_progress(con, session_id).await;
+        if !proceed {
+            break;
+        }
+    }
+    heartbeat.abort();
+    if let Some(exchange) = exchange {
+        exchange.abort();
+    }
+    presence::leave(con, &session.chat_id, &session.robot, Role::Robot).await;
+    metrics::gauge(&metrics::ACTIVE_SESSIONS, -1);
+    eprintln!("Final: {:#?}", session.context);
+}
+
+async fn respond_while_typing(
+    bot: &dyn Bot,
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &Session,
+) -> Result<Turn, BotError> {
+    let typing = tokio::spawn(typing::keep_typing(
+        session.chat_id.clone(),
+        session.robot.clone(),
+        Role::Robot,
+    ));
+    metrics::increment(&metrics::SCRIPT_REQUESTS);
+    let started = tokio::time::Instant::now();
+    let turn = bot.respond(session).await;
+    metrics::observe(&metrics::SCRIPT_LATENCY, started.elapsed());
+    if turn.is_err() {
+        metrics::increment(&metrics::SCRIPT_ERRORS);
+    }
+    typing.abort();
+    typing::publish(con, &session.chat_id, &session.robot, Role::Robot, false).await;
+    turn
+}
+
+async fn on_turn(
+    turn: Turn,
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &mut Session,
+    session_id: &str,
+    guards: &Guards,
+) -> Option<bool> {
+    session
+        .send_user_output_to_redis(
+            con,
+            guards.key.as_ref(),
+            guards.chat_key.as_ref(),
+            turn.user_output,
+        )
+        .await;
+
+    session.context["context"] = turn.context;
+    match turn.command {
+        Command::Wait => match wait_for_user_input(con, session, session_id, guards).await {
+            Waited::Input | Waited::TakenOver => Some(true),
+            Waited::Timeout => {
+                let next = SessionState::Abandoned;
+                if recorded_change(con, session, session_id, next, Action::StateChanged).await {
+                    announce(con, session, guards, SystemEvent::Timeout).await;
+                }
+                Some(false)
+            }
+        },
+        Command::Finish => {
+            session.context = serde_json::json!({});
+            session.save_progress(con, session_id).await;
+            let next = SessionState::Finished;
+            if recorded_change(con, session, session_id, next, Action::Finished).await {
+                announce(con, session, guards, SystemEvent::Finished).await;
+            }
+            Some(false)
+        }
+        Command::Pause => Some(true),
+        Command::Operator => {
+            eprintln!(
+                "Need operator in chat {:?}. {:?}",
+                session.chat_id,
+                session.context["operator_message"].as_str()
+            );
+            session.save_progress(con, session_id).await;
+            let next = SessionState::WaitingOperator;
+            if recorded_change(con, session, session_id, next, Action::Escalated).await {
+                announce(con, session, guards, SystemEvent::OperatorRequested).await;
+            }
+            Some(true)
+        }
+        Command::Noop => {
+            eprint!("NOOP after command.");
+            None
+        }
+    }
+}
+
+async fn change_state(
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &mut Session,
+    session_id: &str,
+    next: SessionState,
+) -> bool {
+    match lifecycle::transition(con, session_id, next).await {
+        Ok(_) => {
+            session.state = next;
+            true
+        }
+        Err(e) => {
+            eprintln!("Session {}: {}", session_id, e);
+            false
+        }
+    }
+}
+
+async fn announce(
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &Session,
+    guards: &Guards,
+    event: SystemEvent,
+) {
+    system::announce(
+        con,
+        &session.chat_id,
+        guards.key.as_ref(),
+        &session.robot,
+        event,
+    )
+    .await;
+}
+
+async fn recorded_change(
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &mut Session,
+    session_id: &str,
+    next: SessionState,
+    action: Action,
+) -> bool {
+    let before = snapshot(con, session_id).await;
+    if !change_state(con, session, session_id, next).await {
+        return false;
+    }
+    let after = snapshot(con, session_id).await;
+    if let Err(e) = audit::record(con, &session.robot, action, session_id, &before, &after).await {
+        eprintln!("Failed to audit {}: {:?}", action.as_str(), e);
+    }
+    true
+}
+
+async fn snapshot(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+) -> serde_json::Value {
+    Session::load(con, session_id)
+        .await
+        .map(|session| audit::session_state(&session))
+        .unwrap_or_default()
+}
+
+async fn resume_after_operator(
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &mut Session,
+    session_id: &str,
+    guards: &Guards,
+) -> bool {
+    eprintln!(
+        "Session {} is handled by an operator, waiting for the hand-back.",
+        session_id
+    );
+    if session.stream_id == "$" {
+        session.stream_id = latest_id(con, &session.chat_id).await;
+    }
+    let stream_id = session.stream_id.clone();
+    let state = handoff::wait_for_hand_back(con, session_id).await;
+    if state != SessionState::BotActive {
+        eprintln!("Session {} is {}, stopping.", session_id, state.as_str());
+        return false;
+    }
+    if let Some(stored) = Session::load(con, session_id).await {
+        *session = stored;
+    }
+    session.stream_id = stream_id;
+    collect_takeover(con, session, guards).await;
+    session.save_progress(con, session_id).await;
+    announce(con, session, guards, SystemEvent::BotResumed).await;
+    true
+}
+
+async fn latest_id(con: &mut redis::aio::MultiplexedConnection, chat_id: &str) -> String {
+    let latest: redis::RedisResult<StreamRangeReply> =
+        con.xrevrange_count(chat_id, "+", "-", 1).await;
+    latest
+        .ok()
+        .and_then(|reply| reply.ids.into_iter().next())
+        .map(|id| id.id)
+        .unwrap_or_else(|| "0-0".to_owned())
+}
+
+async fn collect_takeover(
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &mut Session,
+    guards: &Guards,
+) {
+    let ids = match read_stream_range(
+        con,
+        &session.chat_id,
+        &format!("({}", session.stream_id),
+        "+",
+    )
+    .await
+    {
+        Ok(ids) => ids,
+        Err(e) => {
+            eprintln!("Failed to read the takeover: {:?}", e);
+            metrics::increment(&metrics::REDIS_ERRORS);
+            return;
+        }
+    };
+    let mut input = UserInput::default();
+    let mut operator_messages = vec![];
+    for id in ids {
+        for message in Message::decode_entry(&id.id, id.map) {
+            if message.kind != Kind::Post {
+                continue;
+            }
+            let Some(message) = accept(message, session, guards) else {
+                continue;
+            };
+            if message.from == session.username {
+                input
+                    .push(con, &session.chat_id, guards.chat_key.as_ref(), message)
+                    .await;
+            } else if let Body::Text(text) = message.body {
+                operator_messages.push(serde_json::json!({
+                    "id": message.id,
+                    "from": message.from,
+                    "text": text,
+                }));
+            }
+        }
+        session.stream_id = id.id;
+    }
+    input.store(session);
+    session.context["operator_messages"] = serde_json::Value::Array(operator_messages);
+}
+
+enum Waited {
+    Input,
+    Timeout,
+    TakenOver,
+}
+
+#[derive(Default)]
+struct UserInput {
+    texts: Vec<serde_json::Value>,
+    meta: Vec<serde_json::Value>,
+}
+
+impl UserInput {
+    async fn push(
+        &mut self,
+        con: &mut redis::aio::MultiplexedConnection,
+        chat_id: &str,
+        chat_key: Option<&ChatKey>,
+        message: Message,
+    ) {
+        if let Body::Text(text) = message.body {
+            let reply_to = match message.reply_to.as_deref() {
+                Some(parent) => reply_metadata(con, chat_id, chat_key, parent).await,
+                None => serde_json::Value::Null,
+            };
+            self.texts.push(serde_json::Value::String(text));
+            self.meta.push(serde_json::json!({
+                "id": message.id,
+                "reply_to": reply_to,
+                "attachment": message.attachment,
+            }));
+        }
+    }
+
+    fn store(self, session: &mut Session) {
+        session.context["user_input"] = serde_json::Value::Array(self.texts);
+        session.context["user_input_meta"] = serde_json::Value::Array(self.meta);
+    }
+}
+
+fn accept(mut message: Message, session: &Session, guards: &Guards) -> Option<Message> {
+    if !guards.keyring.is_empty() {
+        let trust = guards.keyring.verify(&session.chat_id, &message);
+        if trust != Trust::Verified {
+            eprintln!("Rejected {:?} entry {}", trust, message.id);
+            metrics::increment(&metrics::MESSAGES_DROPPED);
+            return None;
+        }
+    }
+    if let Some(chat_key) = guards.chat_key.as_ref() {
+        chat_key.open_message(&mut message);
+    }
+    match guards.inbound.inbound(message) {
+        Ok(message) => {
+            metrics::increment(&metrics::MESSAGES_RECEIVED);
+            Some(message)
+        }
+        Err(rejected) => {
+            eprintln!("Dropped by {}: {}", rejected.by, rejected.reason);
+            metrics::increment(&metrics::MESSAGES_DROPPED);
+            None
+        }
+    }
+}
+
+async fn taken_over(
+    con: &mut redis::aio::MultiplexedConnection,
+    message: &Message,
+    session: &Session,
+    session_id: &str,
+) -> bool {
+    if message.kind != Kind::System(SystemEvent::OperatorJoined)
+        || !session.roles_of(&message.from).contains(&Role::Operator)
+    {
+        return false;
+    }
+    matches!(
+        lifecycle::load(con, session_id).await,
+        Ok(SessionState::OperatorActive)
+    )
+}
+
+async fn wait_for_user_input(
+    con: &mut redis::aio::MultiplexedConnection,
+    session: &mut Session,
+    session_id: &str,
+    guards: &Guards,
+) -> Waited {
+    let start = session.stream_id.clone();
+    let mut input = UserInput::default();
+    let deadline = guards
+        .idle_timeout
+        .map(|idle| tokio::time::Instant::now() + idle);
+
+    while input.texts.is_empty() {
+        let read = read_from_stream(con, &session.chat_id, &session.stream_id);
+        let read = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, read).await {
+                Ok(read) => read,
+                Err(_) => {
+                    eprintln!("No user input in chat {:?}, timing out.", session.chat_id);
+                    *con = create_async_redis_connection().await;
+                    return Waited::Timeout;
+                }
+            },
+            None => read.await,
+        };
+        match read {
+            Ok(stream_keys) => {
+                for key in stream_keys {
+                    for id in key.ids {
+                        for message in Message::decode_entry(&id.id, id.map) {
+                            if taken_over(con, &message, session, session_id).await {
+                                session.stream_id = start;
+                                return Waited::TakenOver;
+                            }
+                            if message.kind != Kind::Post || message.from != session.username {
+                                continue;
+                            }
+                            if let Some(message) = accept(message, session, guards) {
+                                input
+                                    .push(con, &session.chat_id, guards.chat_key.as_ref(), message)
+                                    .await;
+                            }
+                        }
+                        session.stream_id = id.id;
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read user input: {:?}", e);
+                metrics::increment(&metrics::REDIS_ERRORS);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                *con = create_async_redis_connection().await;
+            }
+        }
+    }
+    input.store(session);
+    Waited::Input
+}
+
+async fn reply_metadata(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    chat_key: Option<&ChatKey>,
+    parent: &str,
+) -> serde_json::Value {
+    let mut parent_message = read_stream_range(con, chat_id, parent, parent)
+        .await
+        .ok()
+        .and_then(|ids| ids.into_iter().next())
+        .and_then(|id| Message::decode_entry(&id.id, id.map).into_iter().next());
+    if let (Some(message), Some(chat_key)) = (parent_message.as_mut(), chat_key) {
+        chat_key.open_message(message);
+    }
+    match parent_message {
+        Some(message) => serde_json::json!({
+            "id": message.id,
+            "from": message.from,
+            "text": message.body.as_text(),
+        }),
+        None => serde_json::json!({ "id": parent }),
+    }
+}
@@ -1,120 +1,482 @@
-use crate::controller_signals::ControllerSignal;
-use chrono::TimeZone;
+use crate::{
+    attachment::Attachment,
+    controller_signals::ControllerSignal,
+    e2e::{self, ChatKey, ChatKeyWatch, KEY_TIMEOUT},
+    locale::{self, Text},
+    message::{
+        Kind, Message, ATTACHMENT_FIELD, DELETE_FIELD, EDIT_FIELD, ENCRYPTED_FIELD, NOTE_FIELD,
+        REPLY_FIELD,
+    },
+    metrics,
+    middleware::Chain,
+    notes,
+    rate_limit::{Limits, Rejection, TokenBucket},
+    receipts,
+    role::Role,
+    signing::{write_authored, Keyring, SigningKey},
+    system::{self, SystemEvent},
+    typing,
+};
 use redis::{
-    from_redis_value,
-    streams::{StreamKey, StreamRangeReply},
+    streams::{StreamId, StreamKey, StreamRangeReply},
     AsyncCommands,
 };
-use std::{collections::HashMap, hash::BuildHasher};
+use std::{collections::HashMap, hash::BuildHasher, time::Duration};
 use tokio::sync::mpsc;
 
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const OFFLINE_AFTER_FAILURES: u32 = 3;
+
+#[derive(Clone, Copy)]
+struct Inbound<'a> {
+    stream: &'a str,
+    keyring: &'a Keyring,
+    chat: Option<&'a ChatKey>,
+    chain: &'a Chain,
+}
+
 pub enum ConnectorEvent {
-    Post { message: String },
+    Post {
+        message: String,
+        reply_to: Option<String>,
+        attachment: Option<Attachment>,
+    },
+    Edit {
+        target: String,
+        message: String,
+    },
+    Delete {
+        target: String,
+    },
+    Typing {
+        typing: bool,
+    },
+    MarkRead {
+        stream_id: String,
+    },
+    System {
+        event: SystemEvent,
+    },
+    Note {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Online,
+    Reconnecting,
+    Offline,
+}
+
+impl ConnectionState {
+    fn after_failures(failures: u32) -> Self {
+        if failures < OFFLINE_AFTER_FAILURES {
+            Self::Reconnecting
+        } else {
+            Self::Offline
+        }
+    }
 }
 
 pub async fn read_from_stream(
     con: &mut redis::aio::MultiplexedConnection,
     chat_id: &str,
     last_id: &str,
+) -> redis::RedisResult<Vec<StreamKey>> {
+    read_from_streams(con, &[chat_id.to_owned()], &[last_id.to_owned()]).await
+}
+
+pub async fn read_from_streams(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_ids: &[String],
+    last_ids: &[String],
 ) -> redis::RedisResult<Vec<StreamKey>> {
     let opts = redis::streams::StreamReadOptions::default()
         .count(10)
         .block(0);
-    let result: redis::streams::StreamReadReply = con
-        .xread_options(&[chat_id.to_owned()], &[last_id], &opts)
-        .await?;
+    let result: redis::streams::StreamReadReply =
+        con.xread_options(chat_ids, last_ids, &opts).await?;
     Ok(result.keys)
 }
 
+pub async fn read_stream_range(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    start: &str,
+    end: &str,
+) -> redis::RedisResult<Vec<StreamId>> {
+    let reply: StreamRangeReply = con.xrange(chat_id, start, end).await?;
+    Ok(reply.ids)
+}
+
 pub async fn write_to_stream(
     con: &mut redis::aio::MultiplexedConnection,
     chat_id: &str,
     items: &[(&str, &str)],
 ) {
-    let _: redis::RedisResult<()> = con.xadd(chat_id, "*", items).await;
+    let written: redis::RedisResult<()> = con.xadd(chat_id, "*", items).await;
+    if written.is_err() {
+        metrics::increment(&metrics::REDIS_ERRORS);
+    }
+}
+
+pub struct Author {
+    pub username: String,
+    pub role: Role,
+    pub key: Option<SigningKey>,
 }
 
 pub async fn output_connector(
-    username: String,
+    author: Author,
     chat_id: String,
-    mut rx: tokio::sync::mpsc::Receiver<ConnectorEvent>,
+    mut chat_keys: Option<ChatKeyWatch>,
+    mut rx: mpsc::Receiver<ConnectorEvent>,
+    tx: mpsc::Sender<ControllerSignal>,
 ) {
     eprintln!("Output thread begins.");
     let mut con = create_async_redis_connection().await;
     eprintln!("Start output");
+    let limits = Limits::default();
+    let mut bucket = TokenBucket::new(limits);
+    let Author {
+        username,
+        role,
+        key,
+    } = author;
     while let Some(event) = rx.recv().await {
         match event {
-            ConnectorEvent::Post { message } => {
-                write_to_stream(&mut con, &chat_id, &[(username.as_str(), message.as_str())]).await;
+            ConnectorEvent::Post {
+                message,
+                reply_to,
+                attachment,
+            } => {
+                if let Err(rejection) = bucket.check(&message) {
+                    send_rejection(&tx, rejection, Some(message)).await;
+                    continue;
+                }
+                let Ok(chat_key) = e2e::wait_for_key(&mut chat_keys, KEY_TIMEOUT).await else {
+                    let _ = tx
+                        .send(ControllerSignal::KeyTimeout {
+                            draft: Some(message),
+                        })
+                        .await;
+                    continue;
+                };
+                let sealed = encrypt(chat_key.as_ref(), message.clone());
+                let attachment = attachment
+                    .filter(|_| chat_key.is_none())
+                    .and_then(|a| serde_json::to_string(&a).ok());
+                let mut items = vec![];
+                if chat_key.is_some() {
+                    items.push((ENCRYPTED_FIELD, "1"));
+                }
+                if let Some(reply_to) = reply_to.as_deref() {
+                    items.push((REPLY_FIELD, reply_to));
+                }
+                if let Some(attachment) = attachment.as_deref() {
+                    items.push((ATTACHMENT_FIELD, attachment));
+                }
+                let written = timed_write(
+                    &mut con,
+                    &chat_id,
+                    key.as_ref(),
+                    Some(&limits),
+                    &username,
+                    &sealed,
+                    items,
+                )
+                .await;
+                if let Err(rejection) = written {
+                    send_rejection(&tx, rejection, Some(message)).await;
+                }
+            }
+            ConnectorEvent::Edit { target, message } => {
+                if let Err(rejection) = bucket.check(&message) {
+                    send_rejection(&tx, rejection, None).await;
+                    continue;
+                }
+                let Ok(chat_key) = e2e::wait_for_key(&mut chat_keys, KEY_TIMEOUT).await else {
+                    let _ = tx.send(ControllerSignal::KeyTimeout { draft: None }).await;
+                    continue;
+                };
+                let message = encrypt(chat_key.as_ref(), message);
+                let mut items = vec![(EDIT_FIELD, target.as_str())];
+                if chat_key.is_some() {
+                    items.push((ENCRYPTED_FIELD, "1"));
+                }
+                let written = timed_write(
+                    &mut con,
+                    &chat_id,
+                    key.as_ref(),
+                    Some(&limits),
+                    &username,
+                    &message,
+                    items,
+                )
+                .await;
+                if let Err(rejection) = written {
+                    send_rejection(&tx, rejection, None).await;
+                }
+            }
+            ConnectorEvent::Typing { typing } => {
+                typing::publish(&mut con, &chat_id, &username, role, typing).await;
+            }
+            ConnectorEvent::MarkRead { stream_id } => {
+                if let Err(e) = receipts::mark_read(&mut con, &chat_id, &username, &stream_id).await
+                {
+                    eprintln!("Failed to mark read: {:?}", e);
+                }
+            }
+            ConnectorEvent::System { event } => {
+                system::announce(&mut con, &chat_id, key.as_ref(), &username, event).await;
+            }
+            ConnectorEvent::Note { message } => {
+                if !notes::visible_to(role) {
+                    continue;
+                }
+                if let Err(rejection) = bucket.check(&message) {
+                    send_rejection(&tx, rejection, None).await;
+                    continue;
+                }
+                let Ok(chat_key) = e2e::wait_for_key(&mut chat_keys, KEY_TIMEOUT).await else {
+                    let _ = tx.send(ControllerSignal::KeyTimeout { draft: None }).await;
+                    continue;
+                };
+                let message = encrypt(chat_key.as_ref(), message);
+                let mut items = vec![(NOTE_FIELD, "1")];
+                if chat_key.is_some() {
+                    items.push((ENCRYPTED_FIELD, "1"));
+                }
+                let written = timed_write(
+                    &mut con,
+                    &notes::stream_key(&chat_id),
+                    key.as_ref(),
+                    Some(&limits),
+                    &username,
+                    &message,
+                    items,
+                )
+                .await;
+                if let Err(rejection) = written {
+                    send_rejection(&tx, rejection, None).await;
+                }
+            }
+            ConnectorEvent::Delete { target } => {
+                if let Err(rejection) = bucket.check("") {
+                    send_rejection(&tx, rejection, None).await;
+                    continue;
+                }
+                let items = vec![(DELETE_FIELD, target.as_str())];
+                let written = timed_write(
+                    &mut con,
+                    &chat_id,
+                    key.as_ref(),
+                    Some(&limits),
+                    &username,
+                    "",
+                    items,
+                )
+                .await;
+                if let Err(rejection) = written {
+                    send_rejection(&tx, rejection, None).await;
+                }
             }
         }
     }
 }
 
-pub async fn input_connector(chat_id: String, tx: mpsc::Sender<ControllerSignal>) {
+async fn timed_write<'a>(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    key: Option<&SigningKey>,
+    limits: Option<&Limits>,
+    author: &'a str,
+    body: &'a str,
+    items: Vec<(&'a str, &'a str)>,
+) -> Result<(), Rejection> {
+    let started = tokio::time::Instant::now();
+    let written = write_authored(con, chat_id, key, limits, author, body, items).await;
+    metrics::observe(&metrics::WRITE_LATENCY, started.elapsed());
+    if written.is_ok() {
+        metrics::increment(&metrics::MESSAGES_SENT);
+    }
+    written.map(drop)
+}
+
+async fn send_rejection(
+    tx: &mpsc::Sender<ControllerSignal>,
+    rejection: Rejection,
+    draft: Option<String>,
+) {
+    metrics::increment(&metrics::MESSAGES_REJECTED);
+    let _ = tx
+        .send(ControllerSignal::Rejected { rejection, draft })
+        .await;
+}
+
+fn encrypt(chat_key: Option<&ChatKey>, message: String) -> String {
+    match chat_key {
+        Some(chat_key) => chat_key.seal(&message),
+        None => message,
+    }
+}
+
+pub async fn input_connector(
+    chat_id: String,
+    keyring: Keyring,
+    mut chat_keys: Option<ChatKeyWatch>,
+    chain: Chain,
+    tx: mpsc::Sender<ControllerSignal>,
+) {
     eprintln!("Input thread begins.");
-    let mut con = create_async_redis_connection().await;
+    let mut con = connect_with_retry(&tx).await;
     eprintln!("Start input");
     let mut last_id = "$".to_owned();
+    let chat_key = loop {
+        match e2e::wait_for_key(&mut chat_keys, KEY_TIMEOUT).await {
+            Ok(chat_key) => break chat_key,
+            Err(_) => {
+                let _ = tx.send(ControllerSignal::KeyTimeout { draft: None }).await;
+            }
+        }
+    };
+    let inbound = Inbound {
+        stream: &chat_id,
+        keyring: &keyring,
+        chat: chat_key.as_ref(),
+        chain: &chain,
+    };
 
-    read_old_messages(&mut con, &chat_id, tx.clone()).await;
+    read_old_messages(&mut con, &chat_id, inbound, tx.clone()).await;
 
     loop {
         match read_from_stream(&mut con, &chat_id, &last_id).await {
             Ok(result) if !result.is_empty() => {
                 eprintln!("From stream {:?}", result);
-                for key in result {
-                    if let Some(result) = process_input_key(tx.clone(), key).await {
+                for stream_key in result {
+                    if let Some(result) = process_input_key(tx.clone(), inbound, stream_key).await {
                         last_id = result;
                     }
                 }
             }
             Err(e) => {
+                eprintln!("{}: {:?}", locale::tr(Text::RedisError), e);
+                metrics::increment(&metrics::REDIS_ERRORS);
+                send_connection_state(&tx, ConnectionState::Reconnecting).await;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                con = connect_with_retry(&tx).await;
+            }
+            _ => {}
+        }
+    }
+}
+
+pub async fn ping_connector(tx: mpsc::Sender<ControllerSignal>) {
+    let mut con = connect_with_retry(&tx).await;
+    let mut failures = 0;
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    loop {
+        interval.tick().await;
+        let started = tokio::time::Instant::now();
+        let pong: redis::RedisResult<String> = redis::cmd("PING").query_async(&mut con).await;
+        match pong {
+            Ok(_) => {
+                failures = 0;
+                metrics::observe(&metrics::REDIS_LATENCY, started.elapsed());
+                send_connection_state(&tx, ConnectionState::Online).await;
                 let _ = tx
-                    .send(ControllerSignal::Info {
-                        message: format!("REDIS ERROR: {:?}", e),
+                    .send(ControllerSignal::Latency {
+                        latency: started.elapsed(),
                     })
                     .await;
             }
-            _ => {}
+            Err(e) => {
+                eprintln!("PING failed: {:?}", e);
+                metrics::increment(&metrics::REDIS_ERRORS);
+                failures += 1;
+                send_connection_state(&tx, ConnectionState::after_failures(failures)).await;
+                if let Ok(new_con) = try_create_async_redis_connection().await {
+                    con = new_con;
+                }
+            }
         }
     }
 }
 
+pub fn create_redis_client() -> redis::RedisResult<redis::Client> {
+    redis::Client::open("redis://127.0.0.1/")
+}
+
+pub async fn try_create_async_redis_connection(
+) -> redis::RedisResult<redis::aio::MultiplexedConnection> {
+    let client = create_redis_client()?;
+    client.get_multiplexed_tokio_connection().await
+}
+
 pub async fn create_async_redis_connection() -> redis::aio::MultiplexedConnection {
-    let client = redis::Client::open("redis://127.0.0.1/")
-        .map_err(|e| eprintln!("Failed open client: {:?}", e))
-        .unwrap();
-    client
-        .get_multiplexed_tokio_connection()
+    try_create_async_redis_connection()
         .await
         .map_err(|e| eprintln!("Failed get connection: {:?}", e))
         .unwrap()
 }
 
 pub fn create_blocking_redis_connection() -> redis::RedisResult<redis::Connection> {
-    let client = redis::Client::open("redis://127.0.0.1/")?;
+    let client = create_redis_client()?;
     client.get_connection()
 }
 
+pub(crate) async fn connect_with_retry(
+    tx: &mpsc::Sender<ControllerSignal>,
+) -> redis::aio::MultiplexedConnection {
+    send_connection_state(tx, ConnectionState::Connecting).await;
+    let mut failures = 0;
+    loop {
+        match try_create_async_redis_connection().await {
+            Ok(con) => {
+                send_connection_state(tx, ConnectionState::Online).await;
+                return con;
+            }
+            Err(e) => {
+                eprintln!("Failed get connection: {:?}", e);
+                metrics::increment(&metrics::REDIS_ERRORS);
+                failures += 1;
+                send_connection_state(tx, ConnectionState::after_failures(failures)).await;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn send_connection_state(tx: &mpsc::Sender<ControllerSignal>, state: ConnectionState) {
+    let _ = tx.send(ControllerSignal::ConnectionState { state }).await;
+}
+
 async fn read_old_messages(
     con: &mut redis::aio::MultiplexedConnection,
     chat_id: &str,
+    inbound: Inbound<'_>,
     tx: mpsc::Sender<ControllerSignal>,
 ) {
     let prev: Option<StreamRangeReply> = con.xrange_all(chat_id).await.unwrap_or_default();
     if let Some(reply) = prev {
         for stream_id in reply.ids {
             eprintln!("Prev: {:?}", stream_id);
-            process_input_id(tx.clone(), &stream_id.id, stream_id.map).await;
+            process_input_id(tx.clone(), inbound, &stream_id.id, stream_id.map).await;
         }
     }
 }
 
-async fn process_input_key(tx: mpsc::Sender<ControllerSignal>, key: StreamKey) -> Option<String> {
+async fn process_input_key(
+    tx: mpsc::Sender<ControllerSignal>,
+    inbound: Inbound<'_>,
+    stream_key: StreamKey,
+) -> Option<String> {
     let mut last_id = None;
-    for redis::streams::StreamId { id, map } in key.ids {
-        process_input_id(tx.clone(), &id, map).await;
+    for StreamId { id, map } in stream_key.ids {
+        process_input_id(tx.clone(), inbound, &id, map).await;
         last_id = Some(id);
     }
     last_id
@@ -122,36 +484,54 @@ async fn process_input_key(tx: mpsc::Sender<ControllerSignal>, key: StreamKey) -
 
 async fn process_input_id<S: BuildHasher>(
     tx: mpsc::Sender<ControllerSignal>,
+    inbound: Inbound<'_>,
     id: &str,
     map: HashMap<String, redis::Value, S>,
 ) {
-    for (from, message) in map {
-        let _ = tx.send(make_incoming_message(id, from, message)).await;
-    }
-}
-
-fn make_incoming_message(id: &str, from: String, message: redis::Value) -> ControllerSignal {
-    ControllerSignal::IncomingMessage {
-        from,
-        message: format!(
-            "{}. {:?}",
-            make_timestamp_string(id),
-            from_redis_value::<String>(&message)
-                .unwrap_or_else(|e| format!("{:?} ({:?})", message, e))
-        ),
+    for mut message in Message::decode_entry(id, map) {
+        if (message.kind == Kind::Note) != notes::is_stream(inbound.stream) {
+            eprintln!(
+                "Dropped misplaced entry {} on {}",
+                message.id, inbound.stream
+            );
+            metrics::increment(&metrics::MESSAGES_DROPPED);
+            continue;
+        }
+        message.trust = inbound.keyring.verify(inbound.stream, &message);
+        e2e::open_message(inbound.chat, &mut message);
+        if !message.trust.admits(&message.kind) {
+            eprintln!("Dropped {:?} entry {}", message.trust, message.id);
+            metrics::increment(&metrics::MESSAGES_DROPPED);
+            let _ = tx
+                .send(ControllerSignal::Info {
+                    message: format!("{}: {}", locale::tr(Text::DroppedForged), message.from),
+                })
+                .await;
+            continue;
+        }
+        match inbound.chain.inbound(message) {
+            Ok(message) => {
+                metrics::increment(&metrics::MESSAGES_RECEIVED);
+                let _ = tx.send(make_incoming_message(message)).await;
+            }
+            Err(rejected) => {
+                eprintln!("Dropped by {}: {}", rejected.by, rejected.reason);
+                metrics::increment(&metrics::MESSAGES_DROPPED);
+            }
+        }
     }
 }
 
-fn make_timestamp_string(id: &str) -> String {
-    if let Some((timestamp, _)) = id.split_once('-') {
-        format!(
-            "{}",
-            chrono::Local
-                .timestamp_millis_opt(timestamp.parse().unwrap())
-                .unwrap()
-                .format("%d/%m/%Y %H:%M:%S")
-        )
-    } else {
-        String::new()
+fn make_incoming_message(message: Message) -> ControllerSignal {
+    match message.kind.clone() {
+        Kind::Post => ControllerSignal::IncomingMessage { message },
+        Kind::Edit { target } => ControllerSignal::MessageEdited { target, message },
+        Kind::Delete { target } => ControllerSignal::MessageDeleted {
+            target,
+            from: message.from,
+        },
+        Kind::Presence(_) | Kind::System(_) | Kind::Note => {
+            ControllerSignal::IncomingMessage { message }
+        }
     }
 }
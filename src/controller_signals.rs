@@ -1,18 +1,90 @@
+use crate::{
+    attachment::Attachment, connector::ConnectionState, message::Message, presence::Participant,
+    rate_limit::Rejection, role::Role, typing::TypingState,
+};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
 pub enum ControllerSignal {
     IncomingMessage {
+        message: Message,
+    },
+    MessageEdited {
+        target: String,
+        message: Message,
+    },
+    MessageDeleted {
+        target: String,
         from: String,
-        message: String,
     },
     Info {
         message: String,
     },
+    Rejected {
+        rejection: Rejection,
+        draft: Option<String>,
+    },
+    KeyTimeout {
+        draft: Option<String>,
+    },
     ConnectTo {
         username: Option<String>,
         chat_id: Option<String>,
+        role: Role,
+    },
+    ConnectionState {
+        state: ConnectionState,
+    },
+    Latency {
+        latency: Duration,
+    },
+    Participants {
+        participants: Vec<Participant>,
+    },
+    Typing {
+        state: TypingState,
+    },
+    Composing,
+    ReadCursors {
+        cursors: HashMap<String, String>,
     },
     OutgoingMessage {
         message: String,
+        reply_to: Option<String>,
+    },
+    OutgoingEdit {
+        target: String,
+        message: String,
+    },
+    OutgoingDelete {
+        target: String,
+    },
+    QuickReply {
+        value: String,
+    },
+    ManageMessages,
+    ChooseReply,
+    ReplyTo {
+        target: Option<String>,
+    },
+    ChooseFile,
+    SendAttachment {
+        path: PathBuf,
+    },
+    ChooseAttachment,
+    SaveAttachment {
+        attachment: Attachment,
+        path: PathBuf,
+    },
+    ChooseNote,
+    OutgoingNote {
+        message: String,
+    },
+    TakeOver,
+    ChooseHandBack,
+    HandBack {
+        changes: Option<serde_json::Value>,
     },
     Submit,
+    Export,
     Quit,
 }
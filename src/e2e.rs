@@ -0,0 +1,518 @@
+use crate::{
+    message::{Body, Message},
+    signing::{Keyring, SigningKey},
+    utils::{from_hex, to_hex},
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use redis::AsyncCommands;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::sync::watch;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+pub const KEY_DIR_VAR: &str = "TUI_CHAT_KEY_DIR";
+pub const EXCHANGE_INTERVAL: Duration = Duration::from_secs(2);
+pub const KEY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const SEALED: &str = "encrypted";
+
+pub type ChatKeyWatch = watch::Receiver<Option<ChatKey>>;
+
+#[derive(Clone)]
+pub struct ChatKey([u8; KEY_LENGTH]);
+
+impl ChatKey {
+    pub fn generate() -> Self {
+        let mut key = [0; KEY_LENGTH];
+        getrandom::getrandom(&mut key).expect("Failed to read system randomness.");
+        Self(key)
+    }
+
+    pub fn seal(&self, plaintext: &str) -> String {
+        seal(&self.0, plaintext.as_bytes())
+    }
+
+    pub fn open(&self, sealed: &str) -> Option<String> {
+        open(&self.0, sealed).and_then(|plaintext| String::from_utf8(plaintext).ok())
+    }
+
+    pub fn open_message(&self, message: &mut Message) {
+        let Some(sealed) = message.sealed.take() else {
+            return;
+        };
+        message.body = match message.body.as_text().and_then(|body| self.open(body)) {
+            Some(text) => Body::Text(text),
+            None => Body::Undecodable(SEALED.to_owned()),
+        };
+        message.output = sealed
+            .output
+            .and_then(|output| self.open(&output))
+            .and_then(|output| serde_json::from_str(&output).ok());
+    }
+}
+
+pub fn open_message(chat_key: Option<&ChatKey>, message: &mut Message) {
+    match chat_key {
+        Some(chat_key) => chat_key.open_message(message),
+        None => {
+            if message.sealed.take().is_some() {
+                message.body = Body::Undecodable(SEALED.to_owned());
+                message.output = None;
+            }
+        }
+    }
+}
+
+pub struct KeyPair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl KeyPair {
+    pub fn load_or_create(chat_id: &str, name: &str) -> std::io::Result<Self> {
+        let dir = key_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no directory for key files")
+        })?;
+        let path = dir.join(format!("{}-{}.x25519", chat_id, to_hex(name.as_bytes())));
+        let secret = match std::fs::read(&path) {
+            Ok(bytes) => {
+                let bytes: [u8; KEY_LENGTH] = bytes.try_into().map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed key file")
+                })?;
+                StaticSecret::from(bytes)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let secret = StaticSecret::random();
+                std::fs::DirBuilder::new()
+                    .recursive(true)
+                    .mode(0o700)
+                    .create(&dir)?;
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&path)?
+                    .write_all(&secret.to_bytes())?;
+                secret
+            }
+            Err(e) => return Err(e),
+        };
+        let public = PublicKey::from(&secret);
+        Ok(Self { secret, public })
+    }
+
+    fn public_hex(&self) -> String {
+        to_hex(self.public.as_bytes())
+    }
+
+    fn unwrap(&self, wrapped: &WrappedKey) -> Option<ChatKey> {
+        let ephemeral = parse_public_key(&wrapped.ephemeral)?;
+        let shared = self.secret.diffie_hellman(&ephemeral);
+        let wrapping = wrapping_key(shared.as_bytes(), &ephemeral, &self.public);
+        let key: [u8; KEY_LENGTH] = open(&wrapping, &wrapped.sealed)?.try_into().ok()?;
+        Some(ChatKey(key))
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PublishedKey {
+    public: String,
+    signature: String,
+}
+
+impl PublishedKey {
+    fn new(chat_id: &str, name: &str, public: String, identity: &SigningKey) -> Self {
+        let signature = identity.sign_bytes(&encode(&["e2e-key", chat_id, name, &public]));
+        Self { public, signature }
+    }
+
+    fn verify(&self, chat_id: &str, name: &str, keyring: &Keyring) -> bool {
+        let bytes = encode(&["e2e-key", chat_id, name, &self.public]);
+        keyring.verify_bytes(name, &bytes, &self.signature)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WrappedKey {
+    recipient: String,
+    ephemeral: String,
+    sealed: String,
+    wrapper: String,
+    signature: String,
+}
+
+impl WrappedKey {
+    fn new(
+        key: &ChatKey,
+        chat_id: &str,
+        participant: &str,
+        recipient: &PublicKey,
+        wrapper: &str,
+        identity: &SigningKey,
+    ) -> Self {
+        let secret = EphemeralSecret::random();
+        let ephemeral = PublicKey::from(&secret);
+        let shared = secret.diffie_hellman(recipient);
+        let wrapping = wrapping_key(shared.as_bytes(), &ephemeral, recipient);
+        let mut wrapped = Self {
+            recipient: to_hex(recipient.as_bytes()),
+            ephemeral: to_hex(ephemeral.as_bytes()),
+            sealed: seal(&wrapping, &key.0),
+            wrapper: wrapper.to_owned(),
+            signature: String::new(),
+        };
+        wrapped.signature = identity.sign_bytes(&wrapped.signed_bytes(chat_id, participant));
+        wrapped
+    }
+
+    fn verify(&self, chat_id: &str, participant: &str, keyring: &Keyring) -> bool {
+        let bytes = self.signed_bytes(chat_id, participant);
+        keyring.verify_bytes(&self.wrapper, &bytes, &self.signature)
+    }
+
+    fn signed_bytes(&self, chat_id: &str, participant: &str) -> Vec<u8> {
+        encode(&[
+            "e2e-wrap",
+            chat_id,
+            participant,
+            &self.recipient,
+            &self.ephemeral,
+            &self.sealed,
+        ])
+    }
+}
+
+pub struct Identity<'a> {
+    pub name: &'a str,
+    pub key: &'a SigningKey,
+    pub keyring: &'a Keyring,
+}
+
+pub async fn exchange(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    identity: &Identity<'_>,
+    keys: &KeyPair,
+    current: Option<ChatKey>,
+) -> redis::RedisResult<Option<ChatKey>> {
+    let name = identity.name;
+    let public_hex = keys.public_hex();
+    let published = PublishedKey::new(chat_id, name, public_hex.clone(), identity.key);
+    if let Ok(published) = serde_json::to_string(&published) {
+        let _: () = con.hset(public_keys_key(chat_id), name, published).await?;
+    }
+    let chat_key = match current {
+        Some(chat_key) => chat_key,
+        None => {
+            let own: Option<String> = con.hget(wrapped_keys_key(chat_id), name).await?;
+            let unwrapped = own
+                .and_then(|own| serde_json::from_str::<WrappedKey>(&own).ok())
+                .filter(|own| {
+                    own.recipient == public_hex && own.verify(chat_id, name, identity.keyring)
+                })
+                .and_then(|own| keys.unwrap(&own));
+            match unwrapped {
+                Some(chat_key) => chat_key,
+                None => {
+                    let claimed: Option<String> = redis::cmd("SET")
+                        .arg(owner_key(chat_id))
+                        .arg(name)
+                        .arg("NX")
+                        .query_async(con)
+                        .await?;
+                    let owner: Option<String> = con.get(owner_key(chat_id)).await?;
+                    if claimed.is_none() && owner.as_deref() != Some(name) {
+                        return Ok(None);
+                    }
+                    ChatKey::generate()
+                }
+            }
+        }
+    };
+    let public_keys: HashMap<String, String> = con.hgetall(public_keys_key(chat_id)).await?;
+    let wrapped: HashMap<String, String> = con.hgetall(wrapped_keys_key(chat_id)).await?;
+    for (participant, published) in public_keys {
+        let Some(published) = serde_json::from_str::<PublishedKey>(&published)
+            .ok()
+            .filter(|published| published.verify(chat_id, &participant, identity.keyring))
+        else {
+            continue;
+        };
+        let up_to_date = wrapped
+            .get(&participant)
+            .and_then(|wrapped| serde_json::from_str::<WrappedKey>(wrapped).ok())
+            .is_some_and(|wrapped| {
+                wrapped.recipient == published.public
+                    && wrapped.verify(chat_id, &participant, identity.keyring)
+            });
+        if up_to_date {
+            continue;
+        }
+        let Some(public) = parse_public_key(&published.public) else {
+            continue;
+        };
+        let wrapped = WrappedKey::new(
+            &chat_key,
+            chat_id,
+            &participant,
+            &public,
+            name,
+            identity.key,
+        );
+        if let Ok(wrapped) = serde_json::to_string(&wrapped) {
+            let _: () = con
+                .hset(wrapped_keys_key(chat_id), &participant, wrapped)
+                .await?;
+        }
+    }
+    Ok(Some(chat_key))
+}
+
+pub async fn key_exchange(
+    chat_id: String,
+    name: String,
+    identity: SigningKey,
+    keyring: Keyring,
+    keys: KeyPair,
+    key_tx: watch::Sender<Option<ChatKey>>,
+) {
+    let identity = Identity {
+        name: &name,
+        key: &identity,
+        keyring: &keyring,
+    };
+    let mut con = None;
+    let mut interval = tokio::time::interval(EXCHANGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if con.is_none() {
+            con = crate::connector::try_create_async_redis_connection()
+                .await
+                .ok();
+        }
+        let Some(connection) = con.as_mut() else {
+            continue;
+        };
+        let current = key_tx.borrow().clone();
+        match exchange(connection, &chat_id, &identity, &keys, current.clone()).await {
+            Ok(Some(chat_key)) if current.is_none() => {
+                let _ = key_tx.send(Some(chat_key));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Key exchange failed: {:?}", e);
+                con = None;
+            }
+        }
+    }
+}
+
+pub async fn wait_for_key(
+    watch: &mut Option<ChatKeyWatch>,
+    timeout: Duration,
+) -> Result<Option<ChatKey>, tokio::time::error::Elapsed> {
+    let Some(watch) = watch.as_mut() else {
+        return Ok(None);
+    };
+    let chat_key = tokio::time::timeout(timeout, watch.wait_for(Option::is_some)).await?;
+    Ok(chat_key.ok().and_then(|chat_key| chat_key.clone()))
+}
+
+fn seal(key: &[u8; KEY_LENGTH], plaintext: &[u8]) -> String {
+    let mut nonce = [0; NONCE_LENGTH];
+    getrandom::getrandom(&mut nonce).expect("Failed to read system randomness.");
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .expect("ChaCha20-Poly1305 encryption does not fail for in-memory buffers");
+    format!("{}:{}", to_hex(&nonce), to_hex(&ciphertext))
+}
+
+fn open(key: &[u8; KEY_LENGTH], sealed: &str) -> Option<Vec<u8>> {
+    let (nonce, ciphertext) = sealed.split_once(':')?;
+    let nonce = from_hex(nonce).filter(|nonce| nonce.len() == NONCE_LENGTH)?;
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&nonce), from_hex(ciphertext)?.as_slice())
+        .ok()
+}
+
+fn wrapping_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; KEY_LENGTH] {
+    let mut hasher = Sha256::new();
+    hasher.update(shared);
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(recipient.as_bytes());
+    hasher.finalize().into()
+}
+
+fn parse_public_key(hex: &str) -> Option<PublicKey> {
+    let bytes: [u8; KEY_LENGTH] = from_hex(hex)?.try_into().ok()?;
+    Some(PublicKey::from(bytes))
+}
+
+fn key_dir() -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var(KEY_DIR_VAR) {
+        return Some(PathBuf::from(dir));
+    }
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+        })?;
+    Some(data.join("tui_chat").join("keys"))
+}
+
+fn encode(parts: &[&str]) -> Vec<u8> {
+    let mut encoded = vec![];
+    for part in parts {
+        encoded.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        encoded.extend_from_slice(part.as_bytes());
+    }
+    encoded
+}
+
+fn public_keys_key(chat_id: &str) -> String {
+    format!("e2e:{}:keys", chat_id)
+}
+
+fn wrapped_keys_key(chat_id: &str) -> String {
+    format!("e2e:{}:wrapped", chat_id)
+}
+
+fn owner_key(chat_id: &str) -> String {
+    format!("e2e:{}:owner", chat_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::ParticipantKey;
+
+    fn key_pair() -> KeyPair {
+        let secret = StaticSecret::random();
+        let public = PublicKey::from(&secret);
+        KeyPair { secret, public }
+    }
+
+    fn keyring(participants: &[(&str, &SigningKey)]) -> Keyring {
+        let keys: HashMap<String, ParticipantKey> = participants
+            .iter()
+            .map(|(name, key)| {
+                (
+                    name.to_string(),
+                    ParticipantKey {
+                        public: key.public_hex(),
+                        certificate: None,
+                    },
+                )
+            })
+            .collect();
+        Keyring::from_keys("session", &keys, None)
+    }
+
+    #[test]
+    fn sealed_text_opens_with_the_same_key_only() {
+        let chat_key = ChatKey::generate();
+        let sealed = chat_key.seal("hello");
+        assert_ne!(sealed, "hello");
+        assert_eq!(chat_key.open(&sealed).as_deref(), Some("hello"));
+        assert_eq!(ChatKey::generate().open(&sealed), None);
+        let (nonce, ciphertext) = sealed.split_once(':').unwrap();
+        let mut ciphertext = from_hex(ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = format!("{}:{}", nonce, to_hex(&ciphertext));
+        assert_eq!(chat_key.open(&tampered), None);
+    }
+
+    #[test]
+    fn wrapped_key_unwraps_for_its_recipient() {
+        let chat_key = ChatKey::generate();
+        let robot = SigningKey::generate();
+        let recipient = key_pair();
+        let wrapped = WrappedKey::new(
+            &chat_key,
+            "chat",
+            "Customer",
+            &recipient.public,
+            "Robot",
+            &robot,
+        );
+        let unwrapped = recipient.unwrap(&wrapped).unwrap();
+        assert_eq!(unwrapped.0, chat_key.0);
+        assert!(key_pair().unwrap(&wrapped).is_none());
+    }
+
+    #[test]
+    fn wrapped_key_is_checked_against_the_wrapper() {
+        let chat_key = ChatKey::generate();
+        let robot = SigningKey::generate();
+        let recipient = key_pair();
+        let keyring = keyring(&[("Robot", &robot)]);
+        let wrapped = WrappedKey::new(
+            &chat_key,
+            "chat",
+            "Customer",
+            &recipient.public,
+            "Robot",
+            &robot,
+        );
+        assert!(wrapped.verify("chat", "Customer", &keyring));
+        assert!(!wrapped.verify("chat", "Operator", &keyring));
+        let impostor = WrappedKey::new(
+            &chat_key,
+            "chat",
+            "Customer",
+            &recipient.public,
+            "Robot",
+            &SigningKey::generate(),
+        );
+        assert!(!impostor.verify("chat", "Customer", &keyring));
+    }
+
+    #[test]
+    fn published_key_is_bound_to_its_participant() {
+        let customer = SigningKey::generate();
+        let keyring = keyring(&[("Customer", &customer)]);
+        let public = key_pair().public_hex();
+        let published = PublishedKey::new("chat", "Customer", public.clone(), &customer);
+        assert!(published.verify("chat", "Customer", &keyring));
+        assert!(!published.verify("other", "Customer", &keyring));
+        let substituted = PublishedKey {
+            public: key_pair().public_hex(),
+            signature: published.signature.clone(),
+        };
+        assert!(!substituted.verify("chat", "Customer", &keyring));
+        let unknown = PublishedKey::new("chat", "Mallory", public, &SigningKey::generate());
+        assert!(!unknown.verify("chat", "Mallory", &keyring));
+    }
+
+    #[test]
+    fn open_message_marks_undecodable_bodies() {
+        let chat_key = ChatKey::generate();
+        let sealed = chat_key.seal("hello");
+        let fields = [
+            ("Customer", sealed.as_str()),
+            (crate::message::ENCRYPTED_FIELD, "1"),
+        ];
+        let message = Message::from_fields("1-0", &fields).remove(0);
+        let mut opened = message.clone();
+        chat_key.open_message(&mut opened);
+        assert_eq!(opened.body, Body::Text("hello".to_owned()));
+        let mut wrong = message.clone();
+        ChatKey::generate().open_message(&mut wrong);
+        assert!(matches!(wrong.body, Body::Undecodable(_)));
+        let mut keyless = message;
+        open_message(None, &mut keyless);
+        assert_eq!(keyless.body, Body::Undecodable(SEALED.to_owned()));
+        assert!(keyless.sealed.is_none());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    audit::{self, Action},
+    lifecycle::{self, SessionState, StateError},
+};
+use redis::JsonAsyncCommands;
+use std::time::Duration;
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub async fn take_over(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+    actor: &str,
+) -> Result<SessionState, StateError> {
+    let previous = lifecycle::transition(con, session_id, SessionState::OperatorActive).await?;
+    audit_state(
+        con,
+        session_id,
+        actor,
+        Action::TakenOver,
+        previous,
+        SessionState::OperatorActive,
+    )
+    .await;
+    Ok(previous)
+}
+
+pub async fn hand_back(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+    actor: &str,
+    changes: Option<&serde_json::Value>,
+) -> Result<SessionState, StateError> {
+    let current = lifecycle::load(con, session_id).await?;
+    current
+        .transition(SessionState::BotActive)
+        .map_err(StateError::Transition)?;
+    if let Some(changes) = changes.and_then(|changes| changes.as_object()) {
+        let contexts: Option<String> = con
+            .json_get(session_id, "$.context.context")
+            .await
+            .map_err(StateError::Redis)?;
+        let context = merge_context(contexts.as_deref(), changes);
+        let _: () = con
+            .json_set(session_id, "$.context.context", &context)
+            .await
+            .map_err(StateError::Redis)?;
+    }
+    let previous = lifecycle::transition(con, session_id, SessionState::BotActive).await?;
+    audit_state(
+        con,
+        session_id,
+        actor,
+        Action::HandedBack,
+        previous,
+        SessionState::BotActive,
+    )
+    .await;
+    Ok(previous)
+}
+
+fn merge_context(
+    stored: Option<&str>,
+    changes: &serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Value {
+    let mut context = stored
+        .and_then(|contexts| serde_json::from_str::<Vec<serde_json::Value>>(contexts).ok())
+        .and_then(|contexts| contexts.into_iter().next())
+        .filter(serde_json::Value::is_object)
+        .unwrap_or_else(|| serde_json::json!({}));
+    for (key, value) in changes {
+        context[key] = value.clone();
+    }
+    context
+}
+
+pub async fn wait_for_hand_back(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+) -> SessionState {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        match lifecycle::load(con, session_id).await {
+            Ok(SessionState::WaitingOperator | SessionState::OperatorActive) => {}
+            Ok(state) => return state,
+            Err(e) => {
+                eprintln!("Failed to load the session state: {}", e);
+                if let Ok(new_con) = crate::connector::try_create_async_redis_connection().await {
+                    *con = new_con;
+                }
+            }
+        }
+    }
+}
+
+async fn audit_state(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+    actor: &str,
+    action: Action,
+    previous: SessionState,
+    next: SessionState,
+) {
+    let recorded = audit::record_transition(con, actor, action, session_id, previous, next).await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to audit {}: {:?}", action.as_str(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hand_back_changes_override_the_stored_context() {
+        let changes = serde_json::json!({ "step": 3, "note": "refunded" });
+        let changes = changes.as_object().unwrap();
+        assert_eq!(
+            merge_context(Some(r#"[{"step":1,"lang":"en"}]"#), changes),
+            serde_json::json!({ "step": 3, "lang": "en", "note": "refunded" })
+        );
+    }
+
+    #[test]
+    fn hand_back_starts_from_an_empty_context() {
+        let changes = serde_json::json!({ "step": 3 });
+        let changes = changes.as_object().unwrap();
+        let expected = serde_json::json!({ "step": 3 });
+        assert_eq!(merge_context(None, changes), expected);
+        assert_eq!(merge_context(Some("[]"), changes), expected);
+        assert_eq!(merge_context(Some(r#"["text"]"#), changes), expected);
+    }
+}
@@ -1,6 +1,28 @@
 pub mod app;
+pub mod attachment;
+pub mod audit;
+pub mod auth;
+pub mod bot;
 pub mod connector;
 pub mod controller_signals;
+pub mod e2e;
+pub mod handoff;
 pub mod interpret;
+pub mod lifecycle;
+pub mod locale;
+pub mod message;
+pub mod metrics;
+pub mod middleware;
+pub mod monitor;
+pub mod notes;
+pub mod output;
+pub mod presence;
+pub mod rate_limit;
+pub mod receipts;
+pub mod role;
 pub mod session;
+pub mod signing;
+pub mod system;
+pub mod transcript;
+pub mod typing;
 pub mod utils;
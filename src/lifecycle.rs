@@ -0,0 +1,251 @@
+use redis::JsonAsyncCommands;
+
+const TRANSITION_SCRIPT: &str = r#"
+local states = redis.call('JSON.GET', KEYS[1], '$.state')
+if not states then
+    return redis.error_reply('NOTFOUND')
+end
+local current = string.match(states, '"([%w_]+)"') or 'created'
+for i = 2, #ARGV do
+    if ARGV[i] == current then
+        redis.call('JSON.SET', KEYS[1], '$.state', ARGV[1])
+        return current
+    end
+end
+return redis.error_reply('TRANSITION ' .. current)
+"#;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    #[default]
+    Created,
+    BotActive,
+    WaitingOperator,
+    OperatorActive,
+    Paused,
+    Finished,
+    Abandoned,
+}
+
+impl SessionState {
+    pub const ALL: [Self; 7] = [
+        Self::Created,
+        Self::BotActive,
+        Self::WaitingOperator,
+        Self::OperatorActive,
+        Self::Paused,
+        Self::Finished,
+        Self::Abandoned,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::BotActive => "bot_active",
+            Self::WaitingOperator => "waiting_operator",
+            Self::OperatorActive => "operator_active",
+            Self::Paused => "paused",
+            Self::Finished => "finished",
+            Self::Abandoned => "abandoned",
+        }
+    }
+
+    pub fn is_closed(self) -> bool {
+        matches!(self, Self::Finished | Self::Abandoned)
+    }
+
+    pub fn bot_may_run(self) -> bool {
+        matches!(self, Self::Created | Self::BotActive | Self::Paused)
+    }
+
+    pub fn can_become(self, next: Self) -> bool {
+        if self == next {
+            return true;
+        }
+        if self.is_closed() {
+            return false;
+        }
+        if next.is_closed() {
+            return true;
+        }
+        matches!(
+            (self, next),
+            (
+                Self::Created,
+                Self::BotActive | Self::WaitingOperator | Self::OperatorActive
+            ) | (
+                Self::BotActive,
+                Self::WaitingOperator | Self::OperatorActive | Self::Paused
+            ) | (
+                Self::WaitingOperator,
+                Self::BotActive | Self::OperatorActive
+            ) | (Self::OperatorActive, Self::BotActive | Self::Paused)
+                | (
+                    Self::Paused,
+                    Self::BotActive | Self::WaitingOperator | Self::OperatorActive
+                )
+        )
+    }
+
+    pub fn transition(self, next: Self) -> Result<Self, TransitionError> {
+        if self.can_become(next) {
+            Ok(next)
+        } else {
+            Err(TransitionError {
+                from: self,
+                to: next,
+            })
+        }
+    }
+}
+
+impl std::str::FromStr for SessionState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|state| state.as_str() == s.to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown session state {:?}", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionError {
+    pub from: SessionState,
+    pub to: SessionState,
+}
+
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "a {} session cannot become {}",
+            self.from.as_str(),
+            self.to.as_str()
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    Redis(redis::RedisError),
+    NotFound,
+    Transition(TransitionError),
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Redis(e) => write!(f, "{}", e),
+            Self::NotFound => write!(f, "session not found"),
+            Self::Transition(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+pub async fn load(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+) -> Result<SessionState, StateError> {
+    let states: Option<String> = con
+        .json_get(session_id, "$.state")
+        .await
+        .map_err(StateError::Redis)?;
+    let states = states
+        .and_then(|states| serde_json::from_str::<Vec<SessionState>>(&states).ok())
+        .ok_or(StateError::NotFound)?;
+    Ok(states.into_iter().next().unwrap_or_default())
+}
+
+pub async fn transition(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+    next: SessionState,
+) -> Result<SessionState, StateError> {
+    let script = redis::Script::new(TRANSITION_SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(session_id)
+        .arg(serde_json::to_string(&next).unwrap_or_default());
+    for state in SessionState::ALL {
+        if state.can_become(next) {
+            invocation.arg(state.as_str());
+        }
+    }
+    let result: redis::RedisResult<String> = invocation.invoke_async(con).await;
+    match result {
+        Ok(current) => current.parse().map_err(|_| StateError::NotFound),
+        Err(e) => match e.code() {
+            Some("NOTFOUND") => Err(StateError::NotFound),
+            Some("TRANSITION") => Err(StateError::Transition(TransitionError {
+                from: e
+                    .detail()
+                    .and_then(|from| from.trim().parse().ok())
+                    .unwrap_or_default(),
+                to: next,
+            })),
+            _ => Err(StateError::Redis(e)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_states_are_final() {
+        for closed in [SessionState::Finished, SessionState::Abandoned] {
+            for next in SessionState::ALL {
+                assert_eq!(closed.can_become(next), closed == next);
+            }
+        }
+    }
+
+    #[test]
+    fn open_states_may_close() {
+        for state in SessionState::ALL
+            .into_iter()
+            .filter(|state| !state.is_closed())
+        {
+            assert!(state.can_become(SessionState::Finished));
+            assert!(state.can_become(SessionState::Abandoned));
+            assert!(state.can_become(state));
+        }
+    }
+
+    #[test]
+    fn hand_over_follows_the_lifecycle() {
+        use SessionState::*;
+        assert!(Created.can_become(BotActive));
+        assert!(BotActive.can_become(WaitingOperator));
+        assert!(WaitingOperator.can_become(OperatorActive));
+        assert!(WaitingOperator.can_become(BotActive));
+        assert!(BotActive.can_become(OperatorActive));
+        assert!(OperatorActive.can_become(BotActive));
+        assert!(Paused.can_become(BotActive));
+        assert!(!BotActive.can_become(Created));
+        assert!(!WaitingOperator.can_become(Paused));
+        assert!(!OperatorActive.can_become(WaitingOperator));
+        assert_eq!(
+            Finished.transition(BotActive),
+            Err(TransitionError {
+                from: Finished,
+                to: BotActive
+            })
+        );
+    }
+
+    #[test]
+    fn states_round_trip_through_strings() {
+        for state in SessionState::ALL {
+            assert_eq!(state.as_str().parse::<SessionState>(), Ok(state));
+            assert_eq!(
+                serde_json::to_string(&state).unwrap(),
+                format!("\"{}\"", state.as_str())
+            );
+        }
+    }
+}
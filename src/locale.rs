@@ -0,0 +1,359 @@
+use chrono::TimeZone;
+use std::sync::OnceLock;
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    Ru,
+}
+
+impl Locale {
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Self::En),
+            "ru" => Some(Self::Ru),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        ["TUI_CHAT_LOCALE", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .filter(|tag| !tag.is_empty())
+            .find_map(|tag| Self::from_tag(&tag))
+            .unwrap_or(Self::En)
+    }
+
+    pub fn timestamp_format(self) -> &'static str {
+        match self {
+            Self::En => "%m/%d/%Y %I:%M:%S %p",
+            Self::Ru => "%d.%m.%Y %H:%M:%S",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Text {
+    MainViewTitle,
+    EnterMessage,
+    Submit,
+    Disconnect,
+    Ok,
+    EmptyMessage,
+    AlreadyConnected,
+    RedisError,
+    Undecodable,
+    StateConnecting,
+    StateOnline,
+    StateReconnecting,
+    StateOffline,
+    Chat,
+    Role,
+    Latency,
+    RoleCustomer,
+    RoleRobot,
+    RoleOperator,
+    RoleSupervisor,
+    Export,
+    Exported,
+    ExportFailed,
+    OwnMessages,
+    NoOwnMessages,
+    EditMessage,
+    Save,
+    Delete,
+    Cancel,
+    Edited,
+    Deleted,
+    Reply,
+    ReplyTo,
+    ReplyingTo,
+    CancelReply,
+    NothingToReply,
+    ReplyUnavailable,
+    Attach,
+    AttachFile,
+    Send,
+    Files,
+    SaveAs,
+    NoAttachments,
+    AttachFailed,
+    AttachEncrypted,
+    AttachmentSaved,
+    SaveFailed,
+    Participants,
+    Joined,
+    Left,
+    IsTyping,
+    AreTyping,
+    NewMessages,
+    SeenBy,
+    AuthFailed,
+    Unverified,
+    ReplayOf,
+    DroppedForged,
+    KeyUnavailable,
+    KeyTimeout,
+    RateLimited,
+    MessageTooLarge,
+    MessageRejected,
+    ConversationClosed,
+    SystemJoined,
+    SystemOperatorRequested,
+    SystemFinished,
+    SystemTimeout,
+    SystemResumed,
+    SystemHandedBack,
+    TakeOver,
+    HandBack,
+    Note,
+    ReadOnly,
+    SupervisorTitle,
+    Open,
+    NoChats,
+    Encrypted,
+    NoteTitle,
+    NoteLabel,
+    HandBackContext,
+    HandoffFailed,
+    InvalidContext,
+}
+
+pub fn set_locale(locale: Locale) -> bool {
+    LOCALE.set(locale).is_ok()
+}
+
+pub fn current() -> Locale {
+    *LOCALE.get_or_init(Locale::from_env)
+}
+
+pub fn tr(text: Text) -> &'static str {
+    catalogue(current(), text)
+}
+
+pub fn format_timestamp(millis: i64) -> String {
+    chrono::Local
+        .timestamp_millis_opt(millis)
+        .single()
+        .map(|time| time.format(current().timestamp_format()).to_string())
+        .unwrap_or_default()
+}
+
+fn catalogue(locale: Locale, text: Text) -> &'static str {
+    match (locale, text) {
+        (Locale::En, Text::MainViewTitle) => "Main View",
+        (Locale::En, Text::EnterMessage) => "Enter a message:",
+        (Locale::En, Text::Submit) => "Submit",
+        (Locale::En, Text::Disconnect) => "Disconnect",
+        (Locale::En, Text::Ok) => "OK",
+        (Locale::En, Text::EmptyMessage) => {
+            "You are trying to send an empty message to the chat.\nThis is forbidden."
+        }
+        (Locale::En, Text::AlreadyConnected) => {
+            "RUNTIME ERROR:\ntrying to connect when already connected."
+        }
+        (Locale::En, Text::RedisError) => "REDIS ERROR",
+        (Locale::En, Text::Undecodable) => "undecodable payload",
+        (Locale::En, Text::StateConnecting) => "connecting",
+        (Locale::En, Text::StateOnline) => "online",
+        (Locale::En, Text::StateReconnecting) => "reconnecting",
+        (Locale::En, Text::StateOffline) => "offline",
+        (Locale::En, Text::Chat) => "chat",
+        (Locale::En, Text::Role) => "role",
+        (Locale::En, Text::Latency) => "latency",
+        (Locale::En, Text::RoleCustomer) => "customer",
+        (Locale::En, Text::RoleRobot) => "robot",
+        (Locale::En, Text::RoleOperator) => "operator",
+        (Locale::En, Text::RoleSupervisor) => "supervisor",
+        (Locale::En, Text::Export) => "Export",
+        (Locale::En, Text::Exported) => "Transcript exported",
+        (Locale::En, Text::ExportFailed) => "Failed to export transcript",
+        (Locale::En, Text::OwnMessages) => "My messages",
+        (Locale::En, Text::NoOwnMessages) => "You have not sent any messages yet.",
+        (Locale::En, Text::EditMessage) => "Edit message",
+        (Locale::En, Text::Save) => "Save",
+        (Locale::En, Text::Delete) => "Delete",
+        (Locale::En, Text::Cancel) => "Cancel",
+        (Locale::En, Text::Edited) => "edited",
+        (Locale::En, Text::Deleted) => "message deleted",
+        (Locale::En, Text::Reply) => "Reply",
+        (Locale::En, Text::ReplyTo) => "Reply to",
+        (Locale::En, Text::ReplyingTo) => "Replying to",
+        (Locale::En, Text::CancelReply) => "Cancel reply",
+        (Locale::En, Text::NothingToReply) => "There are no messages to reply to.",
+        (Locale::En, Text::ReplyUnavailable) => "message unavailable",
+        (Locale::En, Text::Attach) => "Attach",
+        (Locale::En, Text::AttachFile) => "Path to the file to attach",
+        (Locale::En, Text::Send) => "Send",
+        (Locale::En, Text::Files) => "Files",
+        (Locale::En, Text::SaveAs) => "Save as",
+        (Locale::En, Text::NoAttachments) => "There are no attachments in this chat.",
+        (Locale::En, Text::AttachFailed) => "Failed to attach the file",
+        (Locale::En, Text::AttachEncrypted) => {
+            "Attachments are not encrypted, so they are disabled in encrypted chats"
+        }
+        (Locale::En, Text::AttachmentSaved) => "Attachment saved",
+        (Locale::En, Text::SaveFailed) => "Failed to save the attachment",
+        (Locale::En, Text::Participants) => "Participants",
+        (Locale::En, Text::Joined) => "joined",
+        (Locale::En, Text::Left) => "left",
+        (Locale::En, Text::IsTyping) => "is typing…",
+        (Locale::En, Text::AreTyping) => "are typing…",
+        (Locale::En, Text::NewMessages) => "new messages",
+        (Locale::En, Text::SeenBy) => "seen by",
+        (Locale::En, Text::AuthFailed) => "Authentication failed",
+        (Locale::En, Text::Unverified) => "unverified",
+        (Locale::En, Text::ReplayOf) => "replay of",
+        (Locale::En, Text::DroppedForged) => "Dropped a message with an invalid signature from",
+        (Locale::En, Text::KeyUnavailable) => "Encryption key is unavailable",
+        (Locale::En, Text::KeyTimeout) => "Timed out waiting for the encryption key",
+        (Locale::En, Text::RateLimited) => {
+            "You are sending messages too fast.\nPlease wait a moment and try again."
+        }
+        (Locale::En, Text::MessageTooLarge) => "The message is too large",
+        (Locale::En, Text::MessageRejected) => "The message was rejected",
+        (Locale::En, Text::ConversationClosed) => "conversation closed",
+        (Locale::En, Text::SystemJoined) => "joined the conversation",
+        (Locale::En, Text::SystemOperatorRequested) => "An operator has been requested",
+        (Locale::En, Text::SystemFinished) => "The conversation has ended",
+        (Locale::En, Text::SystemTimeout) => "The session timed out due to inactivity",
+        (Locale::En, Text::SystemResumed) => "resumed the conversation",
+        (Locale::En, Text::SystemHandedBack) => "handed the conversation back",
+        (Locale::En, Text::TakeOver) => "Take over",
+        (Locale::En, Text::HandBack) => "Hand back",
+        (Locale::En, Text::Note) => "Note",
+        (Locale::En, Text::ReadOnly) => "read-only",
+        (Locale::En, Text::SupervisorTitle) => "Live chats",
+        (Locale::En, Text::Open) => "Open",
+        (Locale::En, Text::NoChats) => "No chats to monitor",
+        (Locale::En, Text::Encrypted) => "encrypted",
+        (Locale::En, Text::NoteTitle) => "Internal note (operators only)",
+        (Locale::En, Text::NoteLabel) => "note",
+        (Locale::En, Text::HandBackContext) => "Context changes as a JSON object (optional)",
+        (Locale::En, Text::HandoffFailed) => "Failed to hand the conversation over",
+        (Locale::En, Text::InvalidContext) => "Context changes must be a JSON object",
+        (Locale::Ru, Text::MainViewTitle) => "Главное окно",
+        (Locale::Ru, Text::EnterMessage) => "Введите сообщение:",
+        (Locale::Ru, Text::Submit) => "Отправить",
+        (Locale::Ru, Text::Disconnect) => "Отключиться",
+        (Locale::Ru, Text::Ok) => "OK",
+        (Locale::Ru, Text::EmptyMessage) => {
+            "Вы пытаетесь отправить в чат пустое сообщение.\nЭто запрещено."
+        }
+        (Locale::Ru, Text::AlreadyConnected) => {
+            "ОШИБКА ВЫПОЛНЕНИЯ:\nпопытка подключения при активном подключении."
+        }
+        (Locale::Ru, Text::RedisError) => "ОШИБКА REDIS",
+        (Locale::Ru, Text::Undecodable) => "нечитаемые данные",
+        (Locale::Ru, Text::StateConnecting) => "подключение",
+        (Locale::Ru, Text::StateOnline) => "в сети",
+        (Locale::Ru, Text::StateReconnecting) => "переподключение",
+        (Locale::Ru, Text::StateOffline) => "нет связи",
+        (Locale::Ru, Text::Chat) => "чат",
+        (Locale::Ru, Text::Role) => "роль",
+        (Locale::Ru, Text::Latency) => "задержка",
+        (Locale::Ru, Text::RoleCustomer) => "клиент",
+        (Locale::Ru, Text::RoleRobot) => "робот",
+        (Locale::Ru, Text::RoleOperator) => "оператор",
+        (Locale::Ru, Text::RoleSupervisor) => "супервизор",
+        (Locale::Ru, Text::Export) => "Экспорт",
+        (Locale::Ru, Text::Exported) => "Переписка сохранена",
+        (Locale::Ru, Text::ExportFailed) => "Не удалось сохранить переписку",
+        (Locale::Ru, Text::OwnMessages) => "Мои сообщения",
+        (Locale::Ru, Text::NoOwnMessages) => "Вы ещё не отправили ни одного сообщения.",
+        (Locale::Ru, Text::EditMessage) => "Изменить сообщение",
+        (Locale::Ru, Text::Save) => "Сохранить",
+        (Locale::Ru, Text::Delete) => "Удалить",
+        (Locale::Ru, Text::Cancel) => "Отмена",
+        (Locale::Ru, Text::Edited) => "изменено",
+        (Locale::Ru, Text::Deleted) => "сообщение удалено",
+        (Locale::Ru, Text::Reply) => "Ответить",
+        (Locale::Ru, Text::ReplyTo) => "Ответить на",
+        (Locale::Ru, Text::ReplyingTo) => "Ответ на",
+        (Locale::Ru, Text::CancelReply) => "Отменить ответ",
+        (Locale::Ru, Text::NothingToReply) => "Нет сообщений, на которые можно ответить.",
+        (Locale::Ru, Text::ReplyUnavailable) => "сообщение недоступно",
+        (Locale::Ru, Text::Attach) => "Прикрепить",
+        (Locale::Ru, Text::AttachFile) => "Путь к прикрепляемому файлу",
+        (Locale::Ru, Text::Send) => "Отправить",
+        (Locale::Ru, Text::Files) => "Файлы",
+        (Locale::Ru, Text::SaveAs) => "Сохранить как",
+        (Locale::Ru, Text::NoAttachments) => "В этом чате нет вложений.",
+        (Locale::Ru, Text::AttachFailed) => "Не удалось прикрепить файл",
+        (Locale::Ru, Text::AttachEncrypted) => {
+            "Вложения не шифруются, поэтому в зашифрованных чатах они отключены"
+        }
+        (Locale::Ru, Text::AttachmentSaved) => "Вложение сохранено",
+        (Locale::Ru, Text::SaveFailed) => "Не удалось сохранить вложение",
+        (Locale::Ru, Text::Participants) => "Участники",
+        (Locale::Ru, Text::Joined) => "подключился",
+        (Locale::Ru, Text::Left) => "отключился",
+        (Locale::Ru, Text::IsTyping) => "печатает…",
+        (Locale::Ru, Text::AreTyping) => "печатают…",
+        (Locale::Ru, Text::NewMessages) => "новые сообщения",
+        (Locale::Ru, Text::SeenBy) => "прочитано:",
+        (Locale::Ru, Text::AuthFailed) => "Ошибка аутентификации",
+        (Locale::Ru, Text::Unverified) => "не подтверждено",
+        (Locale::Ru, Text::ReplayOf) => "повтор от",
+        (Locale::Ru, Text::DroppedForged) => "Отброшено сообщение с неверной подписью от",
+        (Locale::Ru, Text::KeyUnavailable) => "Ключ шифрования недоступен",
+        (Locale::Ru, Text::RateLimited) => {
+            "Вы отправляете сообщения слишком часто.\nПодождите немного и попробуйте снова."
+        }
+        (Locale::Ru, Text::MessageTooLarge) => "Сообщение слишком длинное",
+        (Locale::Ru, Text::MessageRejected) => "Сообщение отклонено",
+        (Locale::Ru, Text::ConversationClosed) => "разговор завершён",
+        (Locale::Ru, Text::SystemJoined) => "присоединился к разговору",
+        (Locale::Ru, Text::SystemOperatorRequested) => "Вызван оператор",
+        (Locale::Ru, Text::SystemFinished) => "Разговор завершён",
+        (Locale::Ru, Text::SystemTimeout) => "Сессия завершена из-за неактивности",
+        (Locale::Ru, Text::KeyTimeout) => "Истекло время ожидания ключа шифрования",
+        (Locale::Ru, Text::SystemResumed) => "продолжил разговор",
+        (Locale::Ru, Text::SystemHandedBack) => "вернул разговор роботу",
+        (Locale::Ru, Text::TakeOver) => "Перехватить",
+        (Locale::Ru, Text::HandBack) => "Вернуть роботу",
+        (Locale::Ru, Text::Note) => "Заметка",
+        (Locale::Ru, Text::ReadOnly) => "только чтение",
+        (Locale::Ru, Text::SupervisorTitle) => "Текущие чаты",
+        (Locale::Ru, Text::Open) => "Открыть",
+        (Locale::Ru, Text::NoChats) => "Нет чатов для наблюдения",
+        (Locale::Ru, Text::Encrypted) => "зашифровано",
+        (Locale::Ru, Text::NoteTitle) => "Внутренняя заметка (только для операторов)",
+        (Locale::Ru, Text::NoteLabel) => "заметка",
+        (Locale::Ru, Text::HandBackContext) => {
+            "Изменения контекста в виде JSON-объекта (необязательно)"
+        }
+        (Locale::Ru, Text::HandoffFailed) => "Не удалось передать разговор",
+        (Locale::Ru, Text::InvalidContext) => "Изменения контекста должны быть JSON-объектом",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_locale_tags() {
+        assert_eq!(Locale::from_tag("ru_RU.UTF-8"), Some(Locale::Ru));
+        assert_eq!(Locale::from_tag("en-US"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("C"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("POSIX"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("de_DE"), None);
+        assert_eq!(Locale::from_tag(""), None);
+    }
+
+    #[test]
+    fn catalogues_differ_per_locale() {
+        assert_eq!(catalogue(Locale::En, Text::Submit), "Submit");
+        assert_eq!(catalogue(Locale::Ru, Text::Submit), "Отправить");
+        assert_eq!(catalogue(Locale::Ru, Text::Ok), "OK");
+    }
+}
@@ -0,0 +1,422 @@
+use crate::{
+    attachment::Attachment, output::UserOutput, presence::PresenceEvent, signing::Trust,
+    system::SystemEvent,
+};
+use std::{cmp::Ordering, collections::HashMap, hash::BuildHasher};
+
+pub const EDIT_FIELD: &str = "@edit";
+pub const DELETE_FIELD: &str = "@delete";
+pub const REPLY_FIELD: &str = "@reply";
+pub const ATTACHMENT_FIELD: &str = "@attachment";
+pub const OUTPUT_FIELD: &str = "@output";
+pub const PRESENCE_FIELD: &str = "@presence";
+pub const SIGNATURE_FIELD: &str = "@sig";
+pub const SIGNED_AT_FIELD: &str = "@ts";
+pub const REPLAY_FIELD: &str = "@replay";
+pub const ENCRYPTED_FIELD: &str = "@enc";
+pub const SYSTEM_FIELD: &str = "@system";
+pub const NOTE_FIELD: &str = "@note";
+
+const CONTROL_PREFIX: char = '@';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Text(String),
+    Undecodable(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Post,
+    Edit { target: String },
+    Delete { target: String },
+    Presence(PresenceEvent),
+    System(SystemEvent),
+    Note,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seal {
+    pub signed_at: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sealed {
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: String,
+    pub from: String,
+    pub body: Body,
+    pub kind: Kind,
+    pub reply_to: Option<String>,
+    pub attachment: Option<Attachment>,
+    pub output: Option<UserOutput>,
+    pub seal: Option<Seal>,
+    pub controls: Vec<(String, String)>,
+    pub trust: Trust,
+    pub sealed: Option<Sealed>,
+    pub replay_of: Option<String>,
+    pub annotations: Vec<String>,
+}
+
+impl Message {
+    pub fn decode(id: &str, from: String, value: redis::Value) -> Self {
+        Self {
+            id: id.to_owned(),
+            from,
+            body: Body::decode(value),
+            kind: Kind::Post,
+            reply_to: None,
+            attachment: None,
+            output: None,
+            seal: None,
+            controls: vec![],
+            trust: Trust::Unchecked,
+            sealed: None,
+            replay_of: None,
+            annotations: vec![],
+        }
+    }
+
+    pub fn decode_entry<S: BuildHasher>(
+        id: &str,
+        mut map: HashMap<String, redis::Value, S>,
+    ) -> Vec<Self> {
+        let mut controls: Vec<(String, String)> = map
+            .iter()
+            .filter(|(field, _)| {
+                is_control_field(field)
+                    && ![SIGNATURE_FIELD, SIGNED_AT_FIELD].contains(&field.as_str())
+            })
+            .filter_map(|(field, value)| {
+                Some((field.clone(), redis::from_redis_value(value).ok()?))
+            })
+            .collect();
+        controls.sort();
+        let kind = take_text(&mut map, EDIT_FIELD)
+            .map(|target| Kind::Edit { target })
+            .or_else(|| take_text(&mut map, DELETE_FIELD).map(|target| Kind::Delete { target }))
+            .or_else(|| {
+                take_text(&mut map, PRESENCE_FIELD)
+                    .and_then(|event| PresenceEvent::parse(&event))
+                    .map(Kind::Presence)
+            })
+            .or_else(|| {
+                take_text(&mut map, SYSTEM_FIELD)
+                    .and_then(|event| SystemEvent::parse(&event))
+                    .map(Kind::System)
+            })
+            .or_else(|| take_text(&mut map, NOTE_FIELD).map(|_| Kind::Note))
+            .unwrap_or(Kind::Post);
+        let reply_to = take_text(&mut map, REPLY_FIELD);
+        let replay_of = take_text(&mut map, REPLAY_FIELD);
+        let attachment = take_text(&mut map, ATTACHMENT_FIELD)
+            .and_then(|attachment| serde_json::from_str(&attachment).ok());
+        let encrypted = take_text(&mut map, ENCRYPTED_FIELD).is_some();
+        let raw_output = take_text(&mut map, OUTPUT_FIELD);
+        let (output, sealed) = if encrypted {
+            (None, Some(Sealed { output: raw_output }))
+        } else {
+            let output = raw_output.and_then(|output| serde_json::from_str(&output).ok());
+            (output, None)
+        };
+        let seal = match (
+            take_text(&mut map, SIGNED_AT_FIELD),
+            take_text(&mut map, SIGNATURE_FIELD),
+        ) {
+            (Some(signed_at), Some(signature)) => Some(Seal {
+                signed_at,
+                signature,
+            }),
+            _ => None,
+        };
+        map.into_iter()
+            .filter(|(field, _)| !is_control_field(field))
+            .map(|(from, value)| Self {
+                kind: kind.clone(),
+                reply_to: reply_to.clone(),
+                attachment: attachment.clone(),
+                output: output.clone(),
+                seal: seal.clone(),
+                controls: controls.clone(),
+                sealed: sealed.clone(),
+                replay_of: replay_of.clone(),
+                ..Self::decode(id, from, value)
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_fields(id: &str, fields: &[(&str, &str)]) -> Vec<Self> {
+        let map: HashMap<String, redis::Value> = fields
+            .iter()
+            .map(|(field, value)| {
+                (
+                    field.to_string(),
+                    redis::Value::Data(value.as_bytes().to_vec()),
+                )
+            })
+            .collect();
+        Self::decode_entry(id, map)
+    }
+
+    pub fn timestamp_millis(&self) -> Option<i64> {
+        timestamp_millis(&self.id)
+    }
+}
+
+impl Body {
+    pub fn decode(value: redis::Value) -> Self {
+        match value {
+            redis::Value::Data(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => Self::Text(text),
+                Err(e) => Self::Undecodable(format!("{} bytes", e.as_bytes().len())),
+            },
+            redis::Value::Status(text) => Self::Text(text),
+            redis::Value::Int(n) => Self::Text(n.to_string()),
+            redis::Value::Okay => Self::Text("OK".to_owned()),
+            redis::Value::Nil => Self::Undecodable("nil".to_owned()),
+            redis::Value::Bulk(items) => Self::Undecodable(format!("{} items", items.len())),
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Undecodable(_) => None,
+        }
+    }
+}
+
+pub fn is_control_field(field: &str) -> bool {
+    field.starts_with(CONTROL_PREFIX)
+}
+
+pub fn timestamp_millis(stream_id: &str) -> Option<i64> {
+    stream_id
+        .split_once('-')
+        .and_then(|(timestamp, _)| timestamp.parse().ok())
+}
+
+pub fn stream_id_cmp(a: &str, b: &str) -> Ordering {
+    parse_stream_id(a).cmp(&parse_stream_id(b))
+}
+
+fn parse_stream_id(stream_id: &str) -> Option<(u64, u64)> {
+    let (millis, sequence) = stream_id.split_once('-').unwrap_or((stream_id, "0"));
+    Some((millis.parse().ok()?, sequence.parse().ok()?))
+}
+
+fn take_text<S: BuildHasher>(
+    map: &mut HashMap<String, redis::Value, S>,
+    field: &str,
+) -> Option<String> {
+    map.remove(field)
+        .and_then(|value| redis::from_owned_redis_value(value).ok())
+}
+
+pub fn sanitize(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => skip_escape_sequence(&mut chars),
+            '\u{9b}' => skip_control_sequence(&mut chars),
+            '\u{90}' | '\u{98}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => skip_control_string(&mut chars),
+            '\n' => result.push('\n'),
+            '\t' => result.push_str("    "),
+            c if c.is_control() || is_bidi_control(c) => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn skip_escape_sequence(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    match chars.next() {
+        Some('[') => skip_control_sequence(chars),
+        Some(']' | 'P' | 'X' | '^' | '_') => skip_control_string(chars),
+        Some(' '..='/') => {
+            while chars.next_if(|c| (' '..='/').contains(c)).is_some() {}
+            chars.next_if(|c| ('0'..='~').contains(c));
+        }
+        _ => {}
+    }
+}
+
+fn skip_control_sequence(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    for c in chars.by_ref() {
+        if ('@'..='~').contains(&c) {
+            break;
+        }
+    }
+}
+
+fn skip_control_string(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{7}' | '\u{9c}' => break,
+            '\u{1b}' if chars.peek() == Some(&'\\') => {
+                chars.next();
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_fields_set_the_kind_of_every_message() {
+        let messages =
+            Message::from_fields("1000-0", &[(EDIT_FIELD, "999-0"), ("Customer", "fixed")]);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].from, "Customer");
+        assert_eq!(
+            messages[0].kind,
+            Kind::Edit {
+                target: "999-0".to_owned()
+            }
+        );
+        let messages = Message::from_fields("1000-0", &[(DELETE_FIELD, "999-0"), ("Customer", "")]);
+        assert_eq!(
+            messages[0].kind,
+            Kind::Delete {
+                target: "999-0".to_owned()
+            }
+        );
+        assert_eq!(
+            Message::from_fields("1000-0", &[("@unknown", "x")]).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn output_field_carries_structured_output() {
+        let payload = r#"{"type":"quick_replies","text":"Pick","options":[{"label":"Yes"}]}"#;
+        let messages = Message::from_fields(
+            "1000-0",
+            &[(OUTPUT_FIELD, payload), ("Robot", "Pick\n[Yes]")],
+        );
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            messages[0].output,
+            Some(UserOutput::QuickReplies { .. })
+        ));
+        let messages = Message::from_fields("1000-0", &[(OUTPUT_FIELD, "{"), ("Robot", "text")]);
+        assert_eq!(messages[0].output, None);
+    }
+
+    #[test]
+    fn stream_ids_compare_numerically() {
+        assert_eq!(stream_id_cmp("10-0", "9-5"), Ordering::Greater);
+        assert_eq!(stream_id_cmp("10-2", "10-10"), Ordering::Less);
+        assert_eq!(stream_id_cmp("10", "10-0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn reply_field_links_the_parent() {
+        let messages =
+            Message::from_fields("1000-0", &[(REPLY_FIELD, "999-0"), ("Operator", "yes")]);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].reply_to.as_deref(), Some("999-0"));
+        assert_eq!(messages[0].kind, Kind::Post);
+    }
+
+    #[test]
+    fn sanitize_strips_control_sequences() {
+        assert_eq!(sanitize("\u{1b}[31mred\u{1b}[0m"), "red");
+        assert_eq!(sanitize("\u{1b}[2J\u{1b}[1;1Hclear"), "clear");
+        assert_eq!(sanitize("\u{9b}31mred"), "red");
+    }
+
+    #[test]
+    fn sanitize_strips_operating_system_commands() {
+        assert_eq!(sanitize("\u{1b}]0;title\u{7}text"), "text");
+        assert_eq!(sanitize("\u{1b}]8;;http://x\u{1b}\\link"), "link");
+        assert_eq!(sanitize("\u{9d}0;title\u{9c}text"), "text");
+        assert_eq!(sanitize("\u{1b}Pq#0\u{1b}\\after"), "after");
+    }
+
+    #[test]
+    fn sanitize_strips_escapes_with_intermediate_bytes() {
+        assert_eq!(sanitize("a\u{1b}(Bb"), "ab");
+        assert_eq!(sanitize("a\u{1b}#8b"), "ab");
+        assert_eq!(sanitize("a\u{1b}%Gb"), "ab");
+        assert_eq!(sanitize("a\u{1b} %Fb"), "ab");
+        assert_eq!(sanitize("a\u{1b}cb"), "ab");
+    }
+
+    #[test]
+    fn sanitize_strips_c1_and_bidi_controls() {
+        assert_eq!(sanitize("a\u{85}b\u{7f}c\u{0}d"), "abcd");
+        assert_eq!(sanitize("abc\u{202e}fed\u{202c}"), "abcfed");
+        assert_eq!(sanitize("\u{2066}x\u{2069}\u{200f}"), "x");
+    }
+
+    #[test]
+    fn sanitize_keeps_newlines_and_expands_tabs() {
+        assert_eq!(sanitize("one\ntwo"), "one\ntwo");
+        assert_eq!(sanitize("a\tb"), "a    b");
+        assert_eq!(sanitize("a\r\nb"), "a\nb");
+        assert_eq!(sanitize("привет 👋"), "привет 👋");
+    }
+
+    #[test]
+    fn replayed_entry_keeps_the_original_author_apart() {
+        let messages =
+            Message::from_fields("1000-0", &[(REPLAY_FIELD, "Customer"), ("Replay", "hello")]);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].from, "Replay");
+        assert_eq!(messages[0].replay_of.as_deref(), Some("Customer"));
+        assert_eq!(messages[0].kind, Kind::Post);
+    }
+
+    #[test]
+    fn system_entry_carries_its_event() {
+        let messages = Message::from_fields("1000-0", &[(SYSTEM_FIELD, "finished"), ("Robot", "")]);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].from, "Robot");
+        assert_eq!(messages[0].kind, Kind::System(SystemEvent::Finished));
+    }
+
+    #[test]
+    fn unknown_system_event_is_a_post() {
+        let messages =
+            Message::from_fields("1000-0", &[(SYSTEM_FIELD, "exploded"), ("Robot", "hi")]);
+        assert_eq!(messages[0].kind, Kind::Post);
+    }
+
+    #[test]
+    fn note_entry_is_a_note() {
+        let messages = Message::from_fields(
+            "1000-0",
+            &[(NOTE_FIELD, "1"), ("Operator", "check the refund")],
+        );
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].kind, Kind::Note);
+        assert_eq!(messages[0].body, Body::Text("check the refund".to_owned()));
+        assert!(messages[0].sealed.is_none());
+        let messages = Message::from_fields(
+            "1000-0",
+            &[
+                (NOTE_FIELD, "1"),
+                (ENCRYPTED_FIELD, "1"),
+                ("Operator", "c2VhbGVk"),
+            ],
+        );
+        assert_eq!(messages[0].kind, Kind::Note);
+        assert!(messages[0].sealed.is_some());
+    }
+}
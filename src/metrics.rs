@@ -0,0 +1,287 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, OnceLock, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+pub const ADDR_VAR: &str = "TUI_CHAT_METRICS_ADDR";
+pub const FILE_VAR: &str = "TUI_CHAT_METRICS_FILE";
+pub const DEFAULT_ADDR: &str = "127.0.0.1:0";
+pub const DUMP_INTERVAL: Duration = Duration::from_secs(15);
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const MAX_REQUEST_BYTES: usize = 4096;
+
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+}
+
+pub const MESSAGES_SENT: Metric = Metric {
+    name: "tui_chat_messages_sent_total",
+    help: "Entries written to chat streams.",
+};
+pub const MESSAGES_RECEIVED: Metric = Metric {
+    name: "tui_chat_messages_received_total",
+    help: "Entries read from chat streams and delivered.",
+};
+pub const MESSAGES_REJECTED: Metric = Metric {
+    name: "tui_chat_messages_rejected_total",
+    help: "Outgoing messages refused by rate limits, size limits or middleware.",
+};
+pub const MESSAGES_DROPPED: Metric = Metric {
+    name: "tui_chat_messages_dropped_total",
+    help: "Incoming entries dropped as forged, unauthorised or by middleware.",
+};
+pub const REDIS_ERRORS: Metric = Metric {
+    name: "tui_chat_redis_errors_total",
+    help: "Failed Redis commands and connection attempts.",
+};
+pub const REDIS_LATENCY: Metric = Metric {
+    name: "tui_chat_redis_ping_seconds",
+    help: "Redis PING round trip time.",
+};
+pub const WRITE_LATENCY: Metric = Metric {
+    name: "tui_chat_stream_write_seconds",
+    help: "Time to write an entry to a chat stream.",
+};
+pub const SCRIPT_REQUESTS: Metric = Metric {
+    name: "tui_chat_script_requests_total",
+    help: "Bot turns requested.",
+};
+pub const SCRIPT_ERRORS: Metric = Metric {
+    name: "tui_chat_script_errors_total",
+    help: "Bot turns that failed.",
+};
+pub const SCRIPT_LATENCY: Metric = Metric {
+    name: "tui_chat_script_seconds",
+    help: "Time for the bot to produce a turn.",
+};
+pub const ACTIVE_SESSIONS: Metric = Metric {
+    name: "tui_chat_active_sessions",
+    help: "Sessions currently served or connected.",
+};
+pub const SIGNALS: Metric = Metric {
+    name: "tui_chat_ui_signals_total",
+    help: "Controller signals processed by the widget.",
+};
+pub const SIGNAL_LATENCY: Metric = Metric {
+    name: "tui_chat_ui_signal_batch_seconds",
+    help: "Time the widget spends processing a batch of controller signals.",
+};
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<&'static str, (&'static str, u64)>,
+    gauges: BTreeMap<&'static str, (&'static str, i64)>,
+    histograms: BTreeMap<&'static str, (&'static str, Histogram)>,
+}
+
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn increment(metric: &Metric) {
+    add(metric, 1);
+}
+
+pub fn add(metric: &Metric, value: u64) {
+    registry()
+        .counters
+        .entry(metric.name)
+        .or_insert((metric.help, 0))
+        .1 += value;
+}
+
+pub fn gauge(metric: &Metric, delta: i64) {
+    registry()
+        .gauges
+        .entry(metric.name)
+        .or_insert((metric.help, 0))
+        .1 += delta;
+}
+
+pub fn observe(metric: &Metric, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    let mut registry = registry();
+    let (_, histogram) = registry
+        .histograms
+        .entry(metric.name)
+        .or_insert_with(|| (metric.help, Histogram::default()));
+    for (count, bound) in histogram.buckets.iter_mut().zip(BUCKETS) {
+        if seconds <= *bound {
+            *count += 1;
+        }
+    }
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+pub fn render() -> String {
+    let registry = registry();
+    let mut rendered = String::new();
+    for (name, (help, value)) in &registry.counters {
+        let _ = writeln!(rendered, "# HELP {} {}", name, help);
+        let _ = writeln!(rendered, "# TYPE {} counter", name);
+        let _ = writeln!(rendered, "{} {}", name, value);
+    }
+    for (name, (help, value)) in &registry.gauges {
+        let _ = writeln!(rendered, "# HELP {} {}", name, help);
+        let _ = writeln!(rendered, "# TYPE {} gauge", name);
+        let _ = writeln!(rendered, "{} {}", name, value);
+    }
+    for (name, (help, histogram)) in &registry.histograms {
+        let _ = writeln!(rendered, "# HELP {} {}", name, help);
+        let _ = writeln!(rendered, "# TYPE {} histogram", name);
+        for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(rendered, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(
+            rendered,
+            "{}_bucket{{le=\"+Inf\"}} {}",
+            name, histogram.count
+        );
+        let _ = writeln!(rendered, "{}_sum {}", name, histogram.sum);
+        let _ = writeln!(rendered, "{}_count {}", name, histogram.count);
+    }
+    rendered
+}
+
+pub fn addr_from_env() -> String {
+    std::env::var(ADDR_VAR).unwrap_or_else(|_| DEFAULT_ADDR.to_owned())
+}
+
+pub fn file_from_env() -> Option<PathBuf> {
+    std::env::var(FILE_VAR).ok().map(PathBuf::from)
+}
+
+pub async fn serve(addr: String) {
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to expose metrics on {}: {}", addr, e);
+            return;
+        }
+    };
+    match listener.local_addr() {
+        Ok(bound) => eprintln!("Metrics on http://{}/metrics", bound),
+        Err(_) => eprintln!("Metrics on http://{}/metrics", addr),
+    }
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(respond(stream));
+            }
+            Err(e) => eprintln!("Failed to accept a metrics connection: {}", e),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream) {
+    let mut request = vec![0; MAX_REQUEST_BYTES];
+    let Ok(Ok(read)) = tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut request)).await
+    else {
+        return;
+    };
+    let request = String::from_utf8_lossy(&request[..read]);
+    let path = request
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("GET "))
+        .and_then(|line| line.split_whitespace().next());
+    let (status, body) = match path {
+        Some("/metrics") => ("200 OK", render()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+pub async fn dump_periodically(path: PathBuf, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = dump(&path).await {
+            eprintln!("Failed to dump metrics to {}: {}", path.display(), e);
+        }
+    }
+}
+
+async fn dump(path: &Path) -> std::io::Result<()> {
+    let partial = path.with_extension("partial");
+    tokio::fs::write(&partial, render()).await?;
+    tokio::fs::rename(&partial, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_COUNTER: Metric = Metric {
+        name: "tui_chat_test_counter_total",
+        help: "Counter used by the tests.",
+    };
+    const TEST_LATENCY: Metric = Metric {
+        name: "tui_chat_test_seconds",
+        help: "Histogram used by the tests.",
+    };
+
+    #[test]
+    fn renders_counters_in_the_text_format() {
+        add(&TEST_COUNTER, 2);
+        increment(&TEST_COUNTER);
+        let rendered = render();
+        assert!(
+            rendered.contains("# HELP tui_chat_test_counter_total Counter used by the tests.\n")
+        );
+        assert!(rendered.contains("# TYPE tui_chat_test_counter_total counter\n"));
+        assert!(rendered.contains("\ntui_chat_test_counter_total 3\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        observe(&TEST_LATENCY, Duration::from_millis(20));
+        observe(&TEST_LATENCY, Duration::from_secs(20));
+        let rendered = render();
+        assert!(rendered.contains("tui_chat_test_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(rendered.contains("tui_chat_test_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(rendered.contains("tui_chat_test_seconds_bucket{le=\"10\"} 1\n"));
+        assert!(rendered.contains("tui_chat_test_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("tui_chat_test_seconds_count 2\n"));
+    }
+}
@@ -0,0 +1,362 @@
+use crate::message::{Body, Kind, Message};
+use std::sync::Arc;
+
+const DEFAULT_PROFANITY: &[&str] = &["fuck", "shit", "bitch", "asshole", "bastard"];
+const LINK_PREFIXES: &[&str] = &["http://", "https://", "www."];
+const MIN_NUMBER_DIGITS: usize = 7;
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y.%m.%d", "%d.%m.%Y", "%d-%m-%Y", "%m-%d-%Y"];
+
+pub const PII_MASK_VAR: &str = "TUI_CHAT_PII_MASK";
+
+pub trait Middleware: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn outbound(&self, text: String) -> Result<String, String> {
+        Ok(text)
+    }
+
+    fn inbound(&self, message: Message) -> Result<Message, String> {
+        Ok(message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub by: &'static str,
+    pub reason: String,
+}
+
+#[derive(Clone, Default)]
+pub struct Chain {
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Chain {
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn outbound(&self, text: String) -> Result<String, Rejected> {
+        self.middleware.iter().try_fold(text, |text, middleware| {
+            middleware.outbound(text).map_err(|reason| Rejected {
+                by: middleware.name(),
+                reason,
+            })
+        })
+    }
+
+    pub fn inbound(&self, message: Message) -> Result<Message, Rejected> {
+        if !matches!(message.kind, Kind::Post | Kind::Edit { .. }) {
+            return Ok(message);
+        }
+        self.middleware
+            .iter()
+            .try_fold(message, |message, middleware| {
+                middleware.inbound(message).map_err(|reason| Rejected {
+                    by: middleware.name(),
+                    reason,
+                })
+            })
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    pub outbound: Chain,
+    pub inbound: Chain,
+}
+
+pub struct ProfanityFilter {
+    words: Vec<String>,
+}
+
+impl ProfanityFilter {
+    pub fn new(words: &[&str]) -> Self {
+        Self {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    fn filter(&self, text: &str) -> String {
+        map_words(text, |word| {
+            let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+            if self.words.contains(&bare.to_lowercase()) {
+                Some(word.replace(bare, &"*".repeat(bare.chars().count())))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Default for ProfanityFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROFANITY)
+    }
+}
+
+impl Middleware for ProfanityFilter {
+    fn name(&self) -> &'static str {
+        "profanity"
+    }
+
+    fn outbound(&self, text: String) -> Result<String, String> {
+        Ok(self.filter(&text))
+    }
+
+    fn inbound(&self, message: Message) -> Result<Message, String> {
+        Ok(map_text(message, |text| self.filter(text)))
+    }
+}
+
+pub struct PiiMask;
+
+impl PiiMask {
+    pub fn enabled_from_env() -> bool {
+        std::env::var(PII_MASK_VAR).is_ok_and(|value| matches!(value.as_str(), "1" | "true"))
+    }
+
+    fn mask(text: &str) -> String {
+        map_words(text, |word| {
+            let bare = word.trim_matches(|c: char| matches!(c, ',' | ';' | ':' | '!' | '?'));
+            let bare = bare.trim_end_matches('.');
+            if is_email(bare) {
+                Some(word.replace(bare, "[email]"))
+            } else if is_number(bare) {
+                Some(word.replace(bare, "[number]"))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Middleware for PiiMask {
+    fn name(&self) -> &'static str {
+        "pii"
+    }
+
+    fn outbound(&self, text: String) -> Result<String, String> {
+        Ok(Self::mask(&text))
+    }
+
+    fn inbound(&self, message: Message) -> Result<Message, String> {
+        Ok(map_text(message, Self::mask))
+    }
+}
+
+pub struct LinkDetector;
+
+impl Middleware for LinkDetector {
+    fn name(&self) -> &'static str {
+        "links"
+    }
+
+    fn inbound(&self, mut message: Message) -> Result<Message, String> {
+        let links: Vec<String> = message
+            .body
+            .as_text()
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(link_host)
+            .collect();
+        if !links.is_empty() {
+            message.annotations.push(format!("🔗 {}", links.join(", ")));
+        }
+        Ok(message)
+    }
+}
+
+pub struct SizeLimit {
+    max_bytes: usize,
+}
+
+impl SizeLimit {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes }
+    }
+}
+
+impl Middleware for SizeLimit {
+    fn name(&self) -> &'static str {
+        "size"
+    }
+
+    fn outbound(&self, text: String) -> Result<String, String> {
+        if text.len() > self.max_bytes {
+            Err(format!(
+                "{} bytes, the limit is {}",
+                text.len(),
+                self.max_bytes
+            ))
+        } else {
+            Ok(text)
+        }
+    }
+
+    fn inbound(&self, mut message: Message) -> Result<Message, String> {
+        if let Body::Text(text) = &mut message.body {
+            if text.len() > self.max_bytes {
+                let mut end = self.max_bytes;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                text.truncate(end);
+                text.push('…');
+                message.annotations.push("✂".to_owned());
+            }
+        }
+        Ok(message)
+    }
+}
+
+fn map_text(mut message: Message, f: impl Fn(&str) -> String) -> Message {
+    if let Body::Text(text) = &message.body {
+        message.body = Body::Text(f(text));
+    }
+    if let Some(output) = message.output.as_mut() {
+        output.map_text(&f);
+    }
+    message
+}
+
+fn map_words(text: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    text.split_inclusive(char::is_whitespace)
+        .map(|chunk| {
+            let word = chunk.trim_end_matches(char::is_whitespace);
+            match f(word) {
+                Some(replaced) => format!("{}{}", replaced, &chunk[word.len()..]),
+                None => chunk.to_owned(),
+            }
+        })
+        .collect()
+}
+
+fn is_email(word: &str) -> bool {
+    match word.split_once('@') {
+        Some((user, domain)) => {
+            !user.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+fn is_number(word: &str) -> bool {
+    word.chars().filter(char::is_ascii_digit).count() >= MIN_NUMBER_DIGITS
+        && word
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '(' | ')' | '.'))
+        && !is_date(word)
+}
+
+fn is_date(word: &str) -> bool {
+    DATE_FORMATS
+        .iter()
+        .any(|format| chrono::NaiveDate::parse_from_str(word, format).is_ok())
+}
+
+fn link_host(word: &str) -> Option<String> {
+    let prefix = LINK_PREFIXES
+        .iter()
+        .find(|prefix| word.to_ascii_lowercase().starts_with(*prefix))?;
+    let rest = match *prefix {
+        "www." => word,
+        _ => &word[prefix.len()..],
+    };
+    let host = rest
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(|c: char| !c.is_alphanumeric());
+    (!host.is_empty()).then(|| host.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{QuickReply, UserOutput};
+
+    #[test]
+    fn inbound_filters_apply_to_structured_output() {
+        let mut message = Message::from_fields("1000-0", &[("Robot", "shit happens")]).remove(0);
+        message.output = Some(UserOutput::QuickReplies {
+            text: "shit happens".to_owned(),
+            options: vec![QuickReply {
+                label: "shit".to_owned(),
+                value: None,
+            }],
+        });
+        let chain = Chain::default().with(ProfanityFilter::default());
+        let message = chain.inbound(message).unwrap();
+        assert_eq!(message.body, Body::Text("**** happens".to_owned()));
+        let Some(UserOutput::QuickReplies { text, options }) = message.output else {
+            panic!("output was dropped");
+        };
+        assert_eq!(text, "**** happens");
+        assert_eq!(options[0].label, "****");
+        assert_eq!(options[0].value(), "shit");
+    }
+
+    #[test]
+    fn map_words_keeps_whitespace() {
+        let mapped = map_words("a  b\tc\n", |word| (word == "b").then(|| "B".to_owned()));
+        assert_eq!(mapped, "a  B\tc\n");
+    }
+
+    #[test]
+    fn detects_emails() {
+        assert!(is_email("user@example.com"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("user@localhost"));
+        assert!(!is_email("user@.com"));
+        assert!(!is_email("user@example."));
+        assert!(!is_email("user@a@example.com"));
+    }
+
+    #[test]
+    fn detects_numbers_but_not_dates() {
+        assert!(is_number("+1-555-123-4567"));
+        assert!(is_number("4111111111111111"));
+        assert!(!is_number("12345"));
+        assert!(!is_number("2026-10-19"));
+        assert!(!is_number("19.10.2026"));
+        assert!(!is_number("abc1234567"));
+    }
+
+    #[test]
+    fn masks_pii_and_keeps_punctuation() {
+        assert_eq!(
+            PiiMask::mask("Mail user@example.com, call +1-555-123-4567. On 2026-10-19!"),
+            "Mail [email], call [number]. On 2026-10-19!"
+        );
+    }
+
+    #[test]
+    fn extracts_link_hosts() {
+        assert_eq!(
+            link_host("https://example.com/path?q=1").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            link_host("www.example.com.").as_deref(),
+            Some("www.example.com")
+        );
+        assert_eq!(
+            link_host("HTTP://Example.com").as_deref(),
+            Some("Example.com")
+        );
+        assert_eq!(link_host("https://"), None);
+        assert_eq!(link_host("example.com"), None);
+    }
+
+    #[test]
+    fn profanity_filter_masks_whole_words_only() {
+        let filter = ProfanityFilter::default();
+        assert_eq!(filter.filter("Oh shit, shitake!"), "Oh ****, shitake!");
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    auth::IssuerKey,
+    connector::{read_from_streams, try_create_async_redis_connection},
+    lifecycle::{self, SessionState},
+    message::{Kind, Message},
+    role::Role,
+    session::Session,
+};
+use redis::{streams::StreamRangeReply, AsyncCommands, JsonAsyncCommands};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub const STATE_INTERVAL: Duration = Duration::from_secs(2);
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const LATEST_SCAN: usize = 20;
+const SESSION_TYPE: &str = "ReJSON-RL";
+
+#[derive(Debug, Clone)]
+pub struct Chat {
+    pub session_id: String,
+    pub chat_id: String,
+    pub customer: String,
+    pub state: SessionState,
+    pub latest: Option<Message>,
+    last_id: String,
+}
+
+pub enum MonitorSignal {
+    Activity {
+        chat_id: String,
+        message: Box<Message>,
+    },
+    State {
+        session_id: String,
+        state: SessionState,
+    },
+    Open {
+        session_id: String,
+    },
+    Quit,
+}
+
+pub fn is_activity(message: &Message) -> bool {
+    matches!(message.kind, Kind::Post | Kind::System(_))
+}
+
+pub async fn discover(
+    con: &mut redis::aio::MultiplexedConnection,
+) -> redis::RedisResult<Vec<String>> {
+    let mut iter: redis::AsyncIter<String> = redis::cmd("SCAN")
+        .cursor_arg(0)
+        .arg("TYPE")
+        .arg(SESSION_TYPE)
+        .clone()
+        .iter_async(con)
+        .await?;
+    let mut keys = vec![];
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+pub async fn load(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_ids: &[String],
+    issuer: &IssuerKey,
+    token: Option<&str>,
+) -> Vec<Chat> {
+    let mut chats = vec![];
+    for session_id in session_ids {
+        let sessions: redis::RedisResult<String> = con.json_get(session_id, "$").await;
+        let Some(session) = sessions
+            .ok()
+            .and_then(|sessions| serde_json::from_str::<Vec<Session>>(&sessions).ok())
+            .and_then(|sessions| sessions.into_iter().next())
+        else {
+            continue;
+        };
+        let username = session.username_for(Role::Supervisor);
+        if let Err(e) = issuer.verify(token, session_id, Role::Supervisor, username) {
+            eprintln!("Session {}: {}", session_id, e);
+            continue;
+        }
+        let recent: redis::RedisResult<StreamRangeReply> = con
+            .xrevrange_count(&session.chat_id, "+", "-", LATEST_SCAN)
+            .await;
+        let recent = recent.map(|reply| reply.ids).unwrap_or_default();
+        let last_id = recent
+            .first()
+            .map(|id| id.id.clone())
+            .unwrap_or_else(|| "0-0".to_owned());
+        let latest = recent
+            .into_iter()
+            .flat_map(|id| Message::decode_entry(&id.id, id.map))
+            .find(is_activity);
+        chats.push(Chat {
+            session_id: session_id.clone(),
+            customer: session.username.clone(),
+            chat_id: session.chat_id,
+            state: session.state,
+            latest,
+            last_id,
+        });
+    }
+    chats
+}
+
+pub async fn activity_connector(chats: Vec<Chat>, tx: mpsc::Sender<MonitorSignal>) {
+    let chat_ids: Vec<_> = chats.iter().map(|chat| chat.chat_id.clone()).collect();
+    let mut last_ids: Vec<_> = chats.into_iter().map(|chat| chat.last_id).collect();
+    let mut con = connect().await;
+    loop {
+        match read_from_streams(&mut con, &chat_ids, &last_ids).await {
+            Ok(keys) => {
+                for key in keys {
+                    let Some(n) = chat_ids.iter().position(|chat_id| *chat_id == key.key) else {
+                        continue;
+                    };
+                    for id in key.ids {
+                        last_ids[n] = id.id.clone();
+                        for message in Message::decode_entry(&id.id, id.map) {
+                            if is_activity(&message) {
+                                let _ = tx
+                                    .send(MonitorSignal::Activity {
+                                        chat_id: key.key.clone(),
+                                        message: Box::new(message),
+                                    })
+                                    .await;
+                            }
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read chats: {:?}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                con = connect().await;
+            }
+        }
+    }
+}
+
+pub async fn state_connector(session_ids: Vec<String>, tx: mpsc::Sender<MonitorSignal>) {
+    let mut con = connect().await;
+    let mut interval = tokio::time::interval(STATE_INTERVAL);
+    loop {
+        interval.tick().await;
+        for session_id in &session_ids {
+            match lifecycle::load(&mut con, session_id).await {
+                Ok(state) => {
+                    let _ = tx
+                        .send(MonitorSignal::State {
+                            session_id: session_id.clone(),
+                            state,
+                        })
+                        .await;
+                }
+                Err(e) => eprintln!("Session {}: {}", session_id, e),
+            }
+        }
+    }
+}
+
+async fn connect() -> redis::aio::MultiplexedConnection {
+    loop {
+        match try_create_async_redis_connection().await {
+            Ok(con) => return con,
+            Err(e) => {
+                eprintln!("Failed get connection: {:?}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{PRESENCE_FIELD, SYSTEM_FIELD};
+
+    #[test]
+    fn posts_and_system_events_are_activity() {
+        let post = Message::from_fields("1000-0", &[("Customer", "hello")]);
+        assert!(is_activity(&post[0]));
+        let system = Message::from_fields("1000-0", &[(SYSTEM_FIELD, "finished"), ("Robot", "")]);
+        assert!(is_activity(&system[0]));
+        let presence =
+            Message::from_fields("1000-0", &[(PRESENCE_FIELD, "join"), ("Operator", "")]);
+        assert!(!is_activity(&presence[0]));
+    }
+}
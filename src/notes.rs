@@ -0,0 +1,36 @@
+use crate::role::Role;
+
+const STREAM_PREFIX: &str = "notes:";
+
+pub fn stream_key(chat_id: &str) -> String {
+    format!("{}{}", STREAM_PREFIX, chat_id)
+}
+
+pub fn is_stream(stream: &str) -> bool {
+    stream.starts_with(STREAM_PREFIX)
+}
+
+pub fn visible_to(role: Role) -> bool {
+    matches!(role, Role::Operator | Role::Supervisor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_live_on_their_own_stream() {
+        let key = stream_key("chat-1");
+        assert_eq!(key, "notes:chat-1");
+        assert!(is_stream(&key));
+        assert!(!is_stream("chat-1"));
+    }
+
+    #[test]
+    fn only_staff_see_notes() {
+        assert!(visible_to(Role::Operator));
+        assert!(visible_to(Role::Supervisor));
+        assert!(!visible_to(Role::Customer));
+        assert!(!visible_to(Role::Robot));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    e2e::ChatKey,
+    message::{ENCRYPTED_FIELD, OUTPUT_FIELD},
+    signing::{write_authored, SigningKey},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct QuickReply {
+    pub label: String,
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+impl QuickReply {
+    pub fn value(&self) -> &str {
+        self.value.as_deref().unwrap_or(&self.label)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CardField {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserOutput {
+    Text {
+        text: String,
+    },
+    QuickReplies {
+        text: String,
+        options: Vec<QuickReply>,
+    },
+    Card {
+        title: String,
+        #[serde(default)]
+        text: Option<String>,
+        #[serde(default)]
+        fields: Vec<CardField>,
+    },
+}
+
+impl UserOutput {
+    pub fn from_value(value: serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::String(text) => Some(Self::Text { text }),
+            value @ serde_json::Value::Object(_) => serde_json::from_value(value)
+                .map_err(|e| eprintln!("Unsupported user output: {:?}", e))
+                .ok(),
+            _ => None,
+        }
+    }
+
+    pub fn map_text(&mut self, f: impl Fn(&str) -> String) {
+        match self {
+            Self::Text { text } => *text = f(text),
+            Self::QuickReplies { text, options } => {
+                *text = f(text);
+                for option in options {
+                    option.value.get_or_insert_with(|| option.label.clone());
+                    option.label = f(&option.label);
+                }
+            }
+            Self::Card {
+                title,
+                text,
+                fields,
+            } => {
+                *title = f(title);
+                if let Some(text) = text {
+                    *text = f(text);
+                }
+                for field in fields {
+                    field.name = f(&field.name);
+                    field.value = f(&field.value);
+                }
+            }
+        }
+    }
+
+    pub fn fallback_text(&self) -> String {
+        match self {
+            Self::Text { text } => text.clone(),
+            Self::QuickReplies { text, options } => {
+                let labels: Vec<_> = options
+                    .iter()
+                    .map(|option| format!("[{}]", option.label))
+                    .collect();
+                format!("{}\n{}", text, labels.join(" "))
+            }
+            Self::Card {
+                title,
+                text,
+                fields,
+            } => {
+                let mut fallback = title.clone();
+                if let Some(text) = text {
+                    fallback.push_str(&format!("\n{}", text));
+                }
+                for field in fields {
+                    fallback.push_str(&format!("\n{}: {}", field.name, field.value));
+                }
+                fallback
+            }
+        }
+    }
+}
+
+pub fn parse_user_output(user_output: serde_json::Value) -> Vec<UserOutput> {
+    let output = match user_output {
+        serde_json::Value::Array(a) => a,
+        serde_json::Value::Object(o) if o.contains_key("type") => {
+            vec![serde_json::Value::Object(o)]
+        }
+        serde_json::Value::Object(o) => o.into_iter().map(|(_, v)| v).collect(),
+        v => vec![v],
+    };
+    output
+        .into_iter()
+        .filter_map(UserOutput::from_value)
+        .collect()
+}
+
+pub async fn send_user_output(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    author: &str,
+    key: Option<&SigningKey>,
+    chat_key: Option<&ChatKey>,
+    user_output: serde_json::Value,
+) {
+    let seal = |text: String| match chat_key {
+        Some(chat_key) => chat_key.seal(&text),
+        None => text,
+    };
+    for output in parse_user_output(user_output) {
+        let text = seal(output.fallback_text());
+        let payload = match output {
+            UserOutput::Text { .. } => None,
+            structured => Some(seal(serde_json::to_string(&structured).unwrap_or_default())),
+        };
+        let mut items = vec![];
+        if let Some(payload) = payload.as_deref() {
+            items.push((OUTPUT_FIELD, payload));
+        }
+        if chat_key.is_some() {
+            items.push((ENCRYPTED_FIELD, "1"));
+        }
+        let _ = write_authored(con, chat_id, key, None, author, &text, items).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_arrays_typed_objects_and_plain_strings() {
+        let outputs = parse_user_output(json!([
+            "hello",
+            {"type": "quick_replies", "text": "Pick one", "options": [{"label": "Yes"}, {"label": "No", "value": "n"}]},
+            42
+        ]));
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(
+            outputs[0],
+            UserOutput::Text {
+                text: "hello".to_owned()
+            }
+        );
+        let UserOutput::QuickReplies { options, .. } = &outputs[1] else {
+            panic!("expected quick replies");
+        };
+        assert_eq!(options[0].value(), "Yes");
+        assert_eq!(options[1].value(), "n");
+        let outputs = parse_user_output(json!({"type": "card", "title": "Order"}));
+        assert_eq!(outputs.len(), 1);
+        let outputs = parse_user_output(json!({"first": "a", "second": "b"}));
+        assert_eq!(outputs.len(), 2);
+        assert!(parse_user_output(json!({"type": "carousel"})).is_empty());
+    }
+
+    #[test]
+    fn fallback_text_lists_options_and_fields() {
+        let replies = UserOutput::QuickReplies {
+            text: "Pick one".to_owned(),
+            options: vec![
+                QuickReply {
+                    label: "Yes".to_owned(),
+                    value: None,
+                },
+                QuickReply {
+                    label: "No".to_owned(),
+                    value: None,
+                },
+            ],
+        };
+        assert_eq!(replies.fallback_text(), "Pick one\n[Yes] [No]");
+        let card = UserOutput::Card {
+            title: "Order".to_owned(),
+            text: Some("Shipped".to_owned()),
+            fields: vec![CardField {
+                name: "Id".to_owned(),
+                value: "42".to_owned(),
+            }],
+        };
+        assert_eq!(card.fallback_text(), "Order\nShipped\nId: 42");
+    }
+}
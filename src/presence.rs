@@ -0,0 +1,204 @@
+use crate::{
+    connector::{connect_with_retry, write_to_stream},
+    controller_signals::ControllerSignal,
+    message::PRESENCE_FIELD,
+    role::Role,
+};
+use redis::AsyncCommands;
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::mpsc;
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+pub const HEARTBEAT_TTL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresenceEvent {
+    Join,
+    Leave,
+}
+
+impl PresenceEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Join => "join",
+            Self::Leave => "leave",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "join" => Some(Self::Join),
+            "leave" => Some(Self::Leave),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Participant {
+    pub name: String,
+    pub role: Role,
+    pub last_seen: i64,
+}
+
+pub async fn heartbeat(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    name: &str,
+    role: Role,
+) -> redis::RedisResult<()> {
+    let participant = Participant {
+        name: name.to_owned(),
+        role,
+        last_seen: chrono::Local::now().timestamp_millis(),
+    };
+    let value = serde_json::to_string(&participant).unwrap_or_default();
+    redis::pipe()
+        .hset(presence_key(chat_id), name, value)
+        .ignore()
+        .expire(presence_key(chat_id), HEARTBEAT_TTL.as_secs() as i64)
+        .ignore()
+        .query_async(con)
+        .await
+}
+
+pub async fn announce(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    name: &str,
+    role: Role,
+    event: PresenceEvent,
+) {
+    write_to_stream(
+        con,
+        chat_id,
+        &[(PRESENCE_FIELD, event.as_str()), (name, role.as_str())],
+    )
+    .await;
+}
+
+pub async fn join(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    name: &str,
+    role: Role,
+) {
+    let _ = heartbeat(con, chat_id, name, role).await;
+    announce(con, chat_id, name, role, PresenceEvent::Join).await;
+}
+
+pub async fn leave(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    name: &str,
+    role: Role,
+) {
+    let _: redis::RedisResult<()> = con.hdel(presence_key(chat_id), name).await;
+    announce(con, chat_id, name, role, PresenceEvent::Leave).await;
+}
+
+pub async fn participants(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+) -> redis::RedisResult<Vec<Participant>> {
+    let values: HashMap<String, String> = con.hgetall(presence_key(chat_id)).await?;
+    let alive_since = chrono::Local::now().timestamp_millis() - HEARTBEAT_TTL.as_millis() as i64;
+    let mut participants = vec![];
+    let mut stale = vec![];
+    for (name, value) in values {
+        match serde_json::from_str::<Participant>(&value) {
+            Ok(participant) if participant.last_seen >= alive_since => {
+                participants.push(participant)
+            }
+            _ => stale.push(name),
+        }
+    }
+    if !stale.is_empty() {
+        let _: () = con.hdel(presence_key(chat_id), stale).await?;
+    }
+    participants.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(participants)
+}
+
+pub async fn keep_alive(chat_id: String, name: String, role: Role) {
+    let Ok(mut con) = crate::connector::try_create_async_redis_connection().await else {
+        return;
+    };
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = heartbeat(&mut con, &chat_id, &name, role).await {
+            eprintln!("Heartbeat failed: {:?}", e);
+        }
+    }
+}
+
+pub async fn presence_connector(
+    chat_id: String,
+    name: String,
+    role: Role,
+    tx: mpsc::Sender<ControllerSignal>,
+) {
+    let mut con = connect_with_retry(&tx).await;
+    join(&mut con, &chat_id, &name, role).await;
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = heartbeat(&mut con, &chat_id, &name, role).await {
+            eprintln!("Heartbeat failed: {:?}", e);
+            continue;
+        }
+        if let Ok(participants) = participants(&mut con, &chat_id).await {
+            let _ = tx
+                .send(ControllerSignal::Participants { participants })
+                .await;
+        }
+    }
+}
+
+pub async fn participants_connector(chat_id: String, tx: mpsc::Sender<ControllerSignal>) {
+    let mut con = connect_with_retry(&tx).await;
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Ok(participants) = participants(&mut con, &chat_id).await {
+            let _ = tx
+                .send(ControllerSignal::Participants { participants })
+                .await;
+        }
+    }
+}
+
+fn presence_key(chat_id: &str) -> String {
+    format!("presence:{}", chat_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Kind, Message};
+
+    #[test]
+    fn presence_events_round_trip() {
+        for event in [PresenceEvent::Join, PresenceEvent::Leave] {
+            assert_eq!(PresenceEvent::parse(event.as_str()), Some(event));
+        }
+        assert_eq!(PresenceEvent::parse("away"), None);
+    }
+
+    #[test]
+    fn presence_entries_decode_as_events() {
+        let messages = Message::from_fields(
+            "1000-0",
+            &[(PRESENCE_FIELD, "join"), ("Operator", "operator")],
+        );
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].from, "Operator");
+        assert_eq!(messages[0].kind, Kind::Presence(PresenceEvent::Join));
+    }
+
+    #[test]
+    fn participants_of_a_chat_share_one_key() {
+        assert_eq!(presence_key("chat"), "presence:chat");
+    }
+}
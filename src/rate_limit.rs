@@ -0,0 +1,200 @@
+use crate::metrics;
+use std::time::Instant;
+
+const SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local refill = tonumber(ARGV[2])
+local chat_capacity = tonumber(ARGV[3])
+local chat_refill = tonumber(ARGV[4])
+local max_bytes = tonumber(ARGV[5])
+local size = 0
+for i = 6, #ARGV do
+    size = size + string.len(ARGV[i])
+end
+if size > max_bytes then
+    return redis.error_reply('TOOLARGE ' .. size)
+end
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local function refilled(key, capacity, refill)
+    local bucket = redis.call('HMGET', key, 'tokens', 'updated')
+    local tokens = tonumber(bucket[1]) or capacity
+    local updated = tonumber(bucket[2]) or now
+    return math.min(capacity, tokens + math.max(0, now - updated) * refill / 1000)
+end
+local function store(key, tokens, capacity, refill)
+    redis.call('HSET', key, 'tokens', tostring(tokens), 'updated', now)
+    redis.call('PEXPIRE', key, math.ceil(capacity / refill * 1000))
+end
+local author = refilled(KEYS[2], capacity, refill)
+local chat = refilled(KEYS[3], chat_capacity, chat_refill)
+if author < 1 or chat < 1 then
+    store(KEYS[2], author, capacity, refill)
+    store(KEYS[3], chat, chat_capacity, chat_refill)
+    return redis.error_reply('RATELIMITED')
+end
+store(KEYS[2], author - 1, capacity, refill)
+store(KEYS[3], chat - 1, chat_capacity, chat_refill)
+return redis.call('XADD', KEYS[1], '*', unpack(ARGV, 6))
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub capacity: u32,
+    pub refill_per_second: f64,
+    pub chat_capacity: u32,
+    pub chat_refill_per_second: f64,
+    pub max_message_bytes: usize,
+    pub max_entry_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            capacity: 5,
+            refill_per_second: 1.0,
+            chat_capacity: 20,
+            chat_refill_per_second: 4.0,
+            max_message_bytes: 4 * 1024,
+            max_entry_bytes: 16 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    RateLimited,
+    TooLarge { size: usize, limit: usize },
+    Unavailable,
+}
+
+pub struct TokenBucket {
+    limits: Limits,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            tokens: limits.capacity as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    pub fn check(&mut self, message: &str) -> Result<(), Rejection> {
+        if message.len() > self.limits.max_message_bytes {
+            return Err(Rejection::TooLarge {
+                size: message.len(),
+                limit: self.limits.max_message_bytes,
+            });
+        }
+        let now = Instant::now();
+        let refilled =
+            now.duration_since(self.updated).as_secs_f64() * self.limits.refill_per_second;
+        self.tokens = (self.tokens + refilled).min(self.limits.capacity as f64);
+        self.updated = now;
+        if self.tokens < 1.0 {
+            return Err(Rejection::RateLimited);
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+pub async fn add_entry(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    author: &str,
+    limits: &Limits,
+    items: &[(&str, &str)],
+) -> Result<String, Rejection> {
+    let script = redis::Script::new(SCRIPT);
+    let mut invocation = script.prepare_invoke();
+    invocation
+        .key(chat_id)
+        .key(bucket_key(chat_id, author))
+        .key(chat_bucket_key(chat_id))
+        .arg(limits.capacity)
+        .arg(limits.refill_per_second)
+        .arg(limits.chat_capacity)
+        .arg(limits.chat_refill_per_second)
+        .arg(limits.max_entry_bytes);
+    for (field, value) in items {
+        invocation.arg(*field).arg(*value);
+    }
+    let result: redis::RedisResult<String> = invocation.invoke_async(con).await;
+    match result {
+        Ok(id) => Ok(id),
+        Err(e) => match e.code() {
+            Some("RATELIMITED") => Err(Rejection::RateLimited),
+            Some("TOOLARGE") => Err(Rejection::TooLarge {
+                size: e
+                    .detail()
+                    .and_then(|size| size.trim().parse().ok())
+                    .unwrap_or_default(),
+                limit: limits.max_entry_bytes,
+            }),
+            _ => {
+                eprintln!("Failed to write to stream: {:?}", e);
+                metrics::increment(&metrics::REDIS_ERRORS);
+                Err(Rejection::Unavailable)
+            }
+        },
+    }
+}
+
+fn bucket_key(chat_id: &str, author: &str) -> String {
+    format!("ratelimit:{}:{}", chat_id, author)
+}
+
+fn chat_bucket_key(chat_id: &str) -> String {
+    format!("ratelimit:{}", chat_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn bucket_allows_its_capacity_then_limits() {
+        let limits = Limits::default();
+        let mut bucket = TokenBucket::new(limits);
+        for _ in 0..limits.capacity {
+            assert_eq!(bucket.check("hello"), Ok(()));
+        }
+        assert_eq!(bucket.check("hello"), Err(Rejection::RateLimited));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limits = Limits::default();
+        let mut bucket = TokenBucket::new(limits);
+        for _ in 0..limits.capacity {
+            bucket.check("hello").unwrap();
+        }
+        bucket.updated -= Duration::from_secs(2);
+        assert_eq!(bucket.check("hello"), Ok(()));
+        assert_eq!(bucket.check("hello"), Ok(()));
+        assert_eq!(bucket.check("hello"), Err(Rejection::RateLimited));
+    }
+
+    #[test]
+    fn oversized_message_is_rejected_without_spending_a_token() {
+        let limits = Limits::default();
+        let mut bucket = TokenBucket::new(limits);
+        let message = "x".repeat(limits.max_message_bytes + 1);
+        assert_eq!(
+            bucket.check(&message),
+            Err(Rejection::TooLarge {
+                size: limits.max_message_bytes + 1,
+                limit: limits.max_message_bytes,
+            })
+        );
+        for _ in 0..limits.capacity {
+            assert_eq!(bucket.check("hello"), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{connector::connect_with_retry, controller_signals::ControllerSignal};
+use redis::AsyncCommands;
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::mpsc;
+
+pub const RECEIPTS_INTERVAL: Duration = Duration::from_secs(2);
+
+const MARK_READ_SCRIPT: &str = r"
+local new_ms, new_seq = string.match(ARGV[2], '^(%d+)-(%d+)$')
+if not new_ms then
+    return 0
+end
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if current then
+    local ms, seq = string.match(current, '^(%d+)-(%d+)$')
+    if ms then
+        ms, seq, new_ms, new_seq = tonumber(ms), tonumber(seq), tonumber(new_ms), tonumber(new_seq)
+        if new_ms < ms or (new_ms == ms and new_seq <= seq) then
+            return 0
+        end
+    end
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+return 1
+";
+
+pub async fn mark_read(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    name: &str,
+    stream_id: &str,
+) -> redis::RedisResult<()> {
+    let _: i64 = redis::Script::new(MARK_READ_SCRIPT)
+        .key(receipts_key(chat_id))
+        .arg(name)
+        .arg(stream_id)
+        .invoke_async(con)
+        .await?;
+    Ok(())
+}
+
+pub async fn read_cursors(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+) -> redis::RedisResult<HashMap<String, String>> {
+    con.hgetall(receipts_key(chat_id)).await
+}
+
+pub async fn receipts_connector(chat_id: String, tx: mpsc::Sender<ControllerSignal>) {
+    let mut con = connect_with_retry(&tx).await;
+    let mut last = None;
+    let mut interval = tokio::time::interval(RECEIPTS_INTERVAL);
+    loop {
+        interval.tick().await;
+        match read_cursors(&mut con, &chat_id).await {
+            Ok(cursors) if last.as_ref() != Some(&cursors) => {
+                last = Some(cursors.clone());
+                let _ = tx.send(ControllerSignal::ReadCursors { cursors }).await;
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to read receipts: {:?}", e),
+        }
+    }
+}
+
+fn receipts_key(chat_id: &str) -> String {
+    format!("read:{}", chat_id)
+}
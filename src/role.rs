@@ -0,0 +1,61 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Customer,
+    Robot,
+    Operator,
+    Supervisor,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Customer => "customer",
+            Self::Robot => "robot",
+            Self::Operator => "operator",
+            Self::Supervisor => "supervisor",
+        }
+    }
+
+    pub fn session_path(self) -> &'static str {
+        match self {
+            Self::Customer => "$.username",
+            Self::Robot => "$.robot",
+            Self::Operator => "$.operator",
+            Self::Supervisor => "$.supervisor",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "customer" => Ok(Self::Customer),
+            "robot" => Ok(Self::Robot),
+            "operator" => Ok(Self::Operator),
+            "supervisor" => Ok(Self::Supervisor),
+            _ => Err(format!("unknown role {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in [
+            Role::Customer,
+            Role::Robot,
+            Role::Operator,
+            Role::Supervisor,
+        ] {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+        }
+        assert_eq!("Operator".parse::<Role>(), Ok(Role::Operator));
+        assert!("admin".parse::<Role>().is_err());
+    }
+}
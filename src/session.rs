@@ -1,7 +1,14 @@
 use redis::JsonAsyncCommands;
 use serde_json::json;
+use std::collections::BTreeMap;
 
-use crate::connector::write_to_stream;
+use crate::{
+    e2e::ChatKey,
+    lifecycle::{SessionState, TransitionError},
+    output::send_user_output,
+    role::Role,
+    signing::{ParticipantKey, SigningKey},
+};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Session {
@@ -11,8 +18,22 @@ pub struct Session {
     pub username: String,
     pub robot: String,
     pub operator: String,
+    #[serde(default = "default_supervisor")]
+    pub supervisor: String,
     pub stream_id: String,
     pub context: serde_json::Value,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub keys: BTreeMap<String, ParticipantKey>,
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default)]
+    pub state: SessionState,
+}
+
+fn default_supervisor() -> String {
+    "Supervisor".to_owned()
 }
 
 impl Session {
@@ -25,11 +46,65 @@ impl Session {
             username: "Customer".to_owned(),
             robot: "Robot".to_owned(),
             operator: "Operator".to_owned(),
+            supervisor: default_supervisor(),
             stream_id: "$".to_owned(),
             context: json!({}),
+            locale: None,
+            keys: BTreeMap::new(),
+            encrypted: false,
+            state: SessionState::Created,
         }
     }
 
+    pub fn transition(&mut self, next: SessionState) -> Result<(), TransitionError> {
+        self.state = self.state.transition(next)?;
+        Ok(())
+    }
+
+    pub fn user_input(&self) -> Vec<String> {
+        self.context["user_input"]
+            .as_array()
+            .map(|input| {
+                input
+                    .iter()
+                    .filter_map(|text| text.as_str().map(ToOwned::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn username_for(&self, role: Role) -> &str {
+        match role {
+            Role::Customer => &self.username,
+            Role::Robot => &self.robot,
+            Role::Operator => &self.operator,
+            Role::Supervisor => &self.supervisor,
+        }
+    }
+
+    pub fn roles_of(&self, name: &str) -> Vec<Role> {
+        [
+            Role::Customer,
+            Role::Robot,
+            Role::Operator,
+            Role::Supervisor,
+        ]
+        .into_iter()
+        .filter(|role| self.username_for(*role) == name)
+        .collect()
+    }
+
+    pub async fn load(
+        con: &mut redis::aio::MultiplexedConnection,
+        session_id: &str,
+    ) -> Option<Self> {
+        let sessions: String = con.json_get(session_id, "$").await.ok()?;
+        serde_json::from_str::<Vec<Self>>(&sessions)
+            .ok()?
+            .into_iter()
+            .next()
+    }
+
     pub async fn update_to_redis(
         &mut self,
         con: &mut redis::aio::MultiplexedConnection,
@@ -38,21 +113,45 @@ impl Session {
         let _: redis::RedisResult<()> = con.json_set(session_id, "$", &self).await;
     }
 
+    pub async fn save_progress(
+        &self,
+        con: &mut redis::aio::MultiplexedConnection,
+        session_id: &str,
+    ) {
+        let _: redis::RedisResult<()> = con.json_set(session_id, "$.context", &self.context).await;
+        let _: redis::RedisResult<()> = con
+            .json_set(session_id, "$.stream_id", &self.stream_id)
+            .await;
+    }
+
     pub async fn send_user_output_to_redis(
         &mut self,
         con: &mut redis::aio::MultiplexedConnection,
+        key: Option<&SigningKey>,
+        chat_key: Option<&ChatKey>,
         user_output: serde_json::Value,
     ) {
-        let output = match user_output {
-            serde_json::Value::Array(a) => a,
-            serde_json::Value::Object(o) => o.values().cloned().collect(),
-            v => vec![v],
-        };
-        for msg in output
-            .into_iter()
-            .filter_map(|v| v.as_str().map(ToOwned::to_owned))
-        {
-            write_to_stream(con, &self.chat_id, &[(self.robot.as_str(), msg.as_str())]).await;
-        }
+        send_user_output(con, &self.chat_id, &self.robot, key, chat_key, user_output).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supervisor_has_its_own_identity() {
+        let session = Session::new("script");
+        assert_eq!(session.username_for(Role::Supervisor), "Supervisor");
+        assert_eq!(session.roles_of("Supervisor"), vec![Role::Supervisor]);
+        assert_eq!(session.roles_of("Operator"), vec![Role::Operator]);
+    }
+
+    #[test]
+    fn stored_sessions_without_a_supervisor_get_the_default() {
+        let mut stored = serde_json::to_value(Session::new("script")).unwrap();
+        stored.as_object_mut().unwrap().remove("supervisor");
+        let session: Session = serde_json::from_value(stored).unwrap();
+        assert_eq!(session.supervisor, "Supervisor");
     }
 }
@@ -0,0 +1,359 @@
+use crate::{
+    auth::IssuerKey,
+    message::{Body, Kind, Message, SIGNATURE_FIELD, SIGNED_AT_FIELD},
+    metrics,
+    rate_limit::{add_entry, Limits, Rejection},
+    session::Session,
+    utils::{from_hex, to_hex},
+};
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use redis::AsyncCommands;
+use std::collections::HashMap;
+
+pub const SIGNING_KEY_VAR: &str = "TUI_CHAT_SIGNING_KEY";
+
+const KEY_LENGTH: usize = 32;
+const MAX_CLOCK_SKEW_MILLIS: i64 = 5 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trust {
+    #[default]
+    Unchecked,
+    Verified,
+    Unsigned,
+    Forged,
+}
+
+impl Trust {
+    pub fn admits(self, kind: &Kind) -> bool {
+        !matches!(
+            (self, kind),
+            (Self::Forged, _) | (Self::Unsigned, Kind::Edit { .. } | Kind::Delete { .. })
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ParticipantKey {
+    pub public: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        let mut seed = [0; KEY_LENGTH];
+        getrandom::getrandom(&mut seed).expect("Failed to read system randomness.");
+        Self(ed25519_dalek::SigningKey::from_bytes(&seed))
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let seed: [u8; KEY_LENGTH] = from_hex(hex)?.try_into().ok()?;
+        Some(Self(ed25519_dalek::SigningKey::from_bytes(&seed)))
+    }
+
+    pub fn from_env() -> Option<Self> {
+        std::env::var(SIGNING_KEY_VAR)
+            .ok()
+            .and_then(|hex| Self::from_hex(&hex))
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(self.0.as_bytes())
+    }
+
+    pub fn public_hex(&self) -> String {
+        to_hex(self.0.verifying_key().as_bytes())
+    }
+
+    pub fn sign(
+        &self,
+        stream: &str,
+        author: &str,
+        body: &str,
+        signed_at: i64,
+        controls: &[(&str, &str)],
+    ) -> String {
+        let payload = payload(stream, author, body, signed_at, controls);
+        to_hex(&self.0.sign(&payload).to_bytes())
+    }
+
+    pub fn sign_bytes(&self, bytes: &[u8]) -> String {
+        to_hex(&self.0.sign(bytes).to_bytes())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Keyring(HashMap<String, VerifyingKey>);
+
+impl Keyring {
+    pub fn from_session(session_id: &str, session: &Session) -> Self {
+        Self::from_keys(session_id, &session.keys, IssuerKey::from_env().as_ref())
+    }
+
+    pub fn from_keys<'a>(
+        session_id: &str,
+        keys: impl IntoIterator<Item = (&'a String, &'a ParticipantKey)>,
+        issuer: Option<&IssuerKey>,
+    ) -> Self {
+        Self(
+            keys.into_iter()
+                .filter(|(name, key)| {
+                    issuer.is_none_or(|issuer| {
+                        key.certificate.as_deref().is_some_and(|certificate| {
+                            issuer.check_certificate(certificate, session_id, name, &key.public)
+                        })
+                    })
+                })
+                .filter_map(|(name, key)| Some((name.clone(), parse_public_key(&key.public)?)))
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn verify_bytes(&self, author: &str, bytes: &[u8], signature: &str) -> bool {
+        let Some(key) = self.0.get(author) else {
+            return false;
+        };
+        parse_signature(signature).is_some_and(|signature| key.verify(bytes, &signature).is_ok())
+    }
+
+    pub fn verify(&self, stream: &str, message: &Message) -> Trust {
+        if self.is_empty() {
+            return Trust::Unchecked;
+        }
+        if let Kind::Presence(_) = message.kind {
+            return Trust::Unchecked;
+        }
+        let (Some(key), Some(seal)) = (self.0.get(&message.from), message.seal.as_ref()) else {
+            return Trust::Unsigned;
+        };
+        let (Body::Text(body), Some(signature)) = (&message.body, parse_signature(&seal.signature))
+        else {
+            return Trust::Forged;
+        };
+        let Ok(signed_at) = seal.signed_at.parse::<i64>() else {
+            return Trust::Forged;
+        };
+        let skewed = message
+            .timestamp_millis()
+            .is_none_or(|millis| (millis - signed_at).abs() > MAX_CLOCK_SKEW_MILLIS);
+        if skewed {
+            return Trust::Forged;
+        }
+        let controls: Vec<(&str, &str)> = message
+            .controls
+            .iter()
+            .map(|(field, value)| (field.as_str(), value.as_str()))
+            .collect();
+        let payload = payload(stream, &message.from, body, signed_at, &controls);
+        match key.verify(&payload, &signature) {
+            Ok(()) => Trust::Verified,
+            Err(_) => Trust::Forged,
+        }
+    }
+}
+
+pub async fn write_authored<'a>(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    key: Option<&SigningKey>,
+    limits: Option<&Limits>,
+    author: &'a str,
+    body: &'a str,
+    items: Vec<(&'a str, &'a str)>,
+) -> Result<String, Rejection> {
+    let signed_at = chrono::Utc::now().timestamp_millis();
+    let signature = key.map(|key| key.sign(chat_id, author, body, signed_at, &items));
+    let signed_at = signed_at.to_string();
+    let mut items: Vec<(&str, &str)> = items;
+    items.push((author, body));
+    if let Some(signature) = signature.as_deref() {
+        items.push((SIGNED_AT_FIELD, &signed_at));
+        items.push((SIGNATURE_FIELD, signature));
+    }
+    match limits {
+        Some(limits) => add_entry(con, chat_id, author, limits, &items).await,
+        None => {
+            let written: redis::RedisResult<String> = con.xadd(chat_id, "*", &items).await;
+            written.map_err(|e| {
+                eprintln!("Failed to write to stream: {:?}", e);
+                metrics::increment(&metrics::REDIS_ERRORS);
+                Rejection::Unavailable
+            })
+        }
+    }
+}
+
+fn payload(
+    stream: &str,
+    author: &str,
+    body: &str,
+    signed_at: i64,
+    controls: &[(&str, &str)],
+) -> Vec<u8> {
+    let mut controls = controls.to_vec();
+    controls.sort();
+    let signed_at = signed_at.to_string();
+    let mut payload = vec![];
+    let parts = [stream, author, body, &signed_at]
+        .into_iter()
+        .chain(controls.iter().flat_map(|(field, value)| [*field, *value]));
+    for part in parts {
+        payload.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        payload.extend_from_slice(part.as_bytes());
+    }
+    payload
+}
+
+fn parse_public_key(hex: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; KEY_LENGTH] = from_hex(hex)?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+fn parse_signature(hex: &str) -> Option<Signature> {
+    Signature::from_slice(&from_hex(hex)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{DELETE_FIELD, EDIT_FIELD, REPLY_FIELD};
+
+    fn keyring(name: &str, key: &SigningKey) -> Keyring {
+        let keys = HashMap::from([(
+            name.to_owned(),
+            ParticipantKey {
+                public: key.public_hex(),
+                certificate: None,
+            },
+        )]);
+        Keyring::from_keys("session", &keys, None)
+    }
+
+    fn signed(
+        key: &SigningKey,
+        stream: &str,
+        signed_at: i64,
+        controls: &[(&str, &str)],
+    ) -> Vec<(String, String)> {
+        let signature = key.sign(stream, "Customer", "hello", signed_at, controls);
+        controls
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .chain([
+                ("Customer".to_owned(), "hello".to_owned()),
+                (SIGNED_AT_FIELD.to_owned(), signed_at.to_string()),
+                (SIGNATURE_FIELD.to_owned(), signature),
+            ])
+            .collect()
+    }
+
+    fn as_fields(fields: &[(String, String)]) -> Vec<(&str, &str)> {
+        fields
+            .iter()
+            .map(|(field, value)| (field.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn signed_entry_verifies_on_its_own_stream() {
+        let key = SigningKey::generate();
+        let fields = signed(&key, "chat", 1000, &[(REPLY_FIELD, "1-0")]);
+        let message = Message::from_fields("1000-0", &as_fields(&fields)).remove(0);
+        assert_eq!(
+            keyring("Customer", &key).verify("chat", &message),
+            Trust::Verified
+        );
+        assert_eq!(
+            keyring("Customer", &key).verify("notes:chat", &message),
+            Trust::Forged
+        );
+    }
+
+    #[test]
+    fn changed_control_fields_are_forged() {
+        let key = SigningKey::generate();
+        let mut fields = signed(&key, "chat", 1000, &[(EDIT_FIELD, "1-0")]);
+        fields[0] = (DELETE_FIELD.to_owned(), "1-0".to_owned());
+        let message = Message::from_fields("1000-0", &as_fields(&fields)).remove(0);
+        assert_eq!(
+            keyring("Customer", &key).verify("chat", &message),
+            Trust::Forged
+        );
+        let mut fields = signed(&key, "chat", 1000, &[]);
+        fields.push((REPLY_FIELD.to_owned(), "1-0".to_owned()));
+        let message = Message::from_fields("1000-0", &as_fields(&fields)).remove(0);
+        assert_eq!(
+            keyring("Customer", &key).verify("chat", &message),
+            Trust::Forged
+        );
+    }
+
+    #[test]
+    fn replayed_entry_outside_the_clock_skew_is_forged() {
+        let key = SigningKey::generate();
+        let fields = signed(&key, "chat", 1000, &[]);
+        let replayed = format!("{}-0", 1000 + MAX_CLOCK_SKEW_MILLIS + 1);
+        let message = Message::from_fields(&replayed, &as_fields(&fields)).remove(0);
+        assert_eq!(
+            keyring("Customer", &key).verify("chat", &message),
+            Trust::Forged
+        );
+    }
+
+    #[test]
+    fn other_participants_keys_do_not_verify() {
+        let key = SigningKey::generate();
+        let fields = signed(&key, "chat", 1000, &[]);
+        let message = Message::from_fields("1000-0", &as_fields(&fields)).remove(0);
+        let other = SigningKey::generate();
+        assert_eq!(
+            keyring("Customer", &other).verify("chat", &message),
+            Trust::Forged
+        );
+        assert_eq!(
+            keyring("Operator", &key).verify("chat", &message),
+            Trust::Unsigned
+        );
+        assert_eq!(
+            Keyring::default().verify("chat", &message),
+            Trust::Unchecked
+        );
+    }
+
+    #[test]
+    fn unsigned_edits_and_deletes_are_not_admitted() {
+        let edit = Kind::Edit {
+            target: "1-0".to_owned(),
+        };
+        let delete = Kind::Delete {
+            target: "1-0".to_owned(),
+        };
+        for kind in [&Kind::Post, &edit, &delete] {
+            assert!(Trust::Verified.admits(kind));
+            assert!(Trust::Unchecked.admits(kind));
+            assert!(!Trust::Forged.admits(kind));
+        }
+        assert!(Trust::Unsigned.admits(&Kind::Post));
+        assert!(!Trust::Unsigned.admits(&edit));
+        assert!(!Trust::Unsigned.admits(&delete));
+    }
+
+    #[test]
+    fn unsigned_entry_is_reported() {
+        let key = SigningKey::generate();
+        let message = Message::from_fields("1000-0", &[("Customer", "hello")]).remove(0);
+        assert_eq!(
+            keyring("Customer", &key).verify("chat", &message),
+            Trust::Unsigned
+        );
+    }
+}
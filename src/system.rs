@@ -0,0 +1,124 @@
+use crate::{
+    lifecycle::SessionState,
+    message::SYSTEM_FIELD,
+    session::Session,
+    signing::{write_authored, SigningKey},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemEvent {
+    BotJoined,
+    OperatorRequested,
+    OperatorJoined,
+    BotResumed,
+    HandedBack,
+    Finished,
+    Timeout,
+}
+
+impl SystemEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::BotJoined => "bot_joined",
+            Self::OperatorRequested => "operator_requested",
+            Self::OperatorJoined => "operator_joined",
+            Self::BotResumed => "bot_resumed",
+            Self::HandedBack => "handed_back",
+            Self::Finished => "finished",
+            Self::Timeout => "timeout",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "bot_joined" => Some(Self::BotJoined),
+            "operator_requested" => Some(Self::OperatorRequested),
+            "operator_joined" => Some(Self::OperatorJoined),
+            "bot_resumed" => Some(Self::BotResumed),
+            "handed_back" => Some(Self::HandedBack),
+            "finished" => Some(Self::Finished),
+            "timeout" => Some(Self::Timeout),
+            _ => None,
+        }
+    }
+
+    pub fn for_state(state: SessionState) -> Option<Self> {
+        match state {
+            SessionState::WaitingOperator => Some(Self::OperatorRequested),
+            SessionState::OperatorActive => Some(Self::OperatorJoined),
+            SessionState::BotActive => Some(Self::HandedBack),
+            SessionState::Finished | SessionState::Abandoned => Some(Self::Finished),
+            SessionState::Created | SessionState::Paused => None,
+        }
+    }
+
+    pub fn closes(self) -> bool {
+        matches!(self, Self::Finished | Self::Timeout)
+    }
+}
+
+pub async fn announce(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    key: Option<&SigningKey>,
+    author: &str,
+    event: SystemEvent,
+) {
+    let items = vec![(SYSTEM_FIELD, event.as_str())];
+    let _ = write_authored(con, chat_id, key, None, author, "", items).await;
+}
+
+pub async fn announce_as_operator(
+    con: &mut redis::aio::MultiplexedConnection,
+    session_id: &str,
+    key: Option<&SigningKey>,
+    event: SystemEvent,
+) -> bool {
+    let Some(session) = Session::load(con, session_id).await else {
+        return false;
+    };
+    announce(con, &session.chat_id, key, &session.operator, event).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_round_trip_through_strings() {
+        for event in [
+            SystemEvent::BotJoined,
+            SystemEvent::OperatorRequested,
+            SystemEvent::OperatorJoined,
+            SystemEvent::BotResumed,
+            SystemEvent::HandedBack,
+            SystemEvent::Finished,
+            SystemEvent::Timeout,
+        ] {
+            assert_eq!(SystemEvent::parse(event.as_str()), Some(event));
+        }
+        assert_eq!(SystemEvent::parse("left"), None);
+    }
+
+    #[test]
+    fn only_ending_events_close_the_conversation() {
+        assert!(SystemEvent::Finished.closes());
+        assert!(SystemEvent::Timeout.closes());
+        assert!(!SystemEvent::OperatorJoined.closes());
+        assert!(!SystemEvent::HandedBack.closes());
+        assert_eq!(
+            SystemEvent::for_state(SessionState::Abandoned),
+            Some(SystemEvent::Finished)
+        );
+        assert_eq!(
+            SystemEvent::for_state(SessionState::WaitingOperator),
+            Some(SystemEvent::OperatorRequested)
+        );
+        assert_eq!(
+            SystemEvent::for_state(SessionState::BotActive),
+            Some(SystemEvent::HandedBack)
+        );
+        assert_eq!(SystemEvent::for_state(SessionState::Paused), None);
+    }
+}
@@ -0,0 +1,586 @@
+use crate::{
+    attachment::Attachment,
+    connector::read_stream_range,
+    locale::{self, tr, Text},
+    message::{
+        sanitize, Body, Kind, Message, ATTACHMENT_FIELD, OUTPUT_FIELD, REPLAY_FIELD, REPLY_FIELD,
+    },
+    output::UserOutput,
+    rate_limit::{Limits, Rejection},
+    role::Role,
+    session::Session,
+    signing::{write_authored, Keyring, SigningKey, Trust},
+};
+use std::{collections::HashMap, time::Duration};
+
+pub const REDACTABLE_FIELDS: &[&str] = &["id", "timestamp", "author", "role", "body", "attachment"];
+
+const REDACTED: &str = "[REDACTED]";
+const REPLAY_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    Markdown,
+    PlainText,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::JsonLines => "jsonl",
+            Self::Markdown => "md",
+            Self::PlainText => "txt",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" | "jsonl" | "jsonlines" => Ok(Self::JsonLines),
+            "md" | "markdown" => Ok(Self::Markdown),
+            "txt" | "text" | "plain" => Ok(Self::PlainText),
+            _ => Err(format!("unknown export format {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayMode {
+    Bulk,
+    Timed { speed: f64 },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub redact: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub id: String,
+    pub timestamp: Option<i64>,
+    pub author: String,
+    pub role: Option<Role>,
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<UserOutput>,
+    #[serde(default)]
+    pub trust: Trust,
+}
+
+impl Entry {
+    pub fn from_message(message: Message, roles: &HashMap<String, Role>) -> Self {
+        Self {
+            timestamp: message.timestamp_millis(),
+            role: roles.get(&message.from).copied(),
+            body: match message.body {
+                Body::Text(text) if message.sealed.is_none() => Some(text),
+                _ => None,
+            },
+            id: message.id,
+            author: message.from,
+            reply_to: message.reply_to,
+            attachment: message.attachment,
+            output: message.output,
+            trust: message.trust,
+        }
+    }
+
+    pub fn redact(&mut self, field: &str) -> Result<(), String> {
+        match field {
+            "id" => {
+                self.id = REDACTED.to_owned();
+                self.reply_to = None;
+            }
+            "timestamp" => self.timestamp = None,
+            "author" => self.author = REDACTED.to_owned(),
+            "role" => self.role = None,
+            "body" => {
+                self.body = Some(REDACTED.to_owned());
+                self.output = None;
+            }
+            "attachment" => self.attachment = None,
+            _ => return Err(format!("cannot redact unknown field {:?}", field)),
+        }
+        Ok(())
+    }
+}
+
+pub fn roles_from_session(session: &Session) -> HashMap<String, Role> {
+    HashMap::from([
+        (session.username.clone(), Role::Customer),
+        (session.robot.clone(), Role::Robot),
+        (session.operator.clone(), Role::Operator),
+        (session.supervisor.clone(), Role::Supervisor),
+    ])
+}
+
+fn apply(entries: &mut Vec<Entry>, message: Message, roles: &HashMap<String, Role>) {
+    if !message.trust.admits(&message.kind) {
+        return;
+    }
+    match message.kind.clone() {
+        Kind::Post => entries.push(Entry::from_message(message, roles)),
+        Kind::Edit { target } => {
+            if let Some(entry) = entries.iter_mut().find(|entry| {
+                entry.id == target
+                    && entry.author == message.from
+                    && (entry.trust != Trust::Verified || message.trust == Trust::Verified)
+            }) {
+                entry.trust = message.trust;
+                entry.body = message
+                    .body
+                    .as_text()
+                    .filter(|_| message.sealed.is_none())
+                    .map(ToOwned::to_owned);
+                entry.output = None;
+            }
+        }
+        Kind::Delete { target } => {
+            entries.retain(|entry| !(entry.id == target && entry.author == message.from))
+        }
+        Kind::Presence(_) | Kind::System(_) | Kind::Note => {}
+    }
+}
+
+pub async fn read_transcript(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    roles: &HashMap<String, Role>,
+    keyring: &Keyring,
+    options: &ExportOptions,
+) -> Result<Vec<Entry>, String> {
+    if let Some(field) = options
+        .redact
+        .iter()
+        .find(|field| !REDACTABLE_FIELDS.contains(&field.as_str()))
+    {
+        return Err(format!("cannot redact unknown field {:?}", field));
+    }
+    let start = options
+        .since
+        .map(|since| format!("{}-0", since))
+        .unwrap_or_else(|| "-".to_owned());
+    let end = options
+        .until
+        .map(|until| until.to_string())
+        .unwrap_or_else(|| "+".to_owned());
+    let ids = read_stream_range(con, chat_id, &start, &end)
+        .await
+        .map_err(|e| format!("{}: {:?}", tr(Text::RedisError), e))?;
+    let mut entries: Vec<Entry> = vec![];
+    for stream_id in ids {
+        for mut message in Message::decode_entry(&stream_id.id, stream_id.map) {
+            message.trust = keyring.verify(chat_id, &message);
+            apply(&mut entries, message, roles);
+        }
+    }
+    for entry in entries.iter_mut() {
+        for field in options.redact.iter() {
+            entry.redact(field)?;
+        }
+    }
+    Ok(entries)
+}
+
+pub fn export(chat_id: &str, entries: &[Entry], format: Format) -> String {
+    match format {
+        Format::JsonLines => entries
+            .iter()
+            .filter_map(|entry| serde_json::to_string(entry).ok())
+            .map(|line| line + "\n")
+            .collect(),
+        Format::Markdown => {
+            let mut output = format!("# {} {}\n", tr(Text::Chat), chat_id);
+            for entry in entries {
+                output.push_str(&format!("\n**{}**", sanitize(&entry.author)));
+                if let Some(role) = entry.role {
+                    output.push_str(&format!(" ({})", role.as_str()));
+                }
+                if let Some(timestamp) = entry.timestamp {
+                    output.push_str(&format!(" — _{}_", locale::format_timestamp(timestamp)));
+                }
+                output.push_str("\n\n");
+                if let Some(quote) = reply_quote(entries, entry) {
+                    output.push_str(&format!("> ↳ _{}_\n>\n", quote));
+                }
+                for line in body_text(entry).lines() {
+                    output.push_str(&format!("> {}\n", line));
+                }
+                if let Some(attachment) = entry.attachment.as_ref() {
+                    output.push_str(&format!(
+                        "> 📎 {} `sha256:{}`\n",
+                        sanitize(&attachment.describe()),
+                        attachment.sha256
+                    ));
+                }
+            }
+            output
+        }
+        Format::PlainText => {
+            let mut output = String::new();
+            for entry in entries {
+                if let Some(timestamp) = entry.timestamp {
+                    output.push_str(&format!("[{}] ", locale::format_timestamp(timestamp)));
+                }
+                output.push_str(&sanitize(&entry.author));
+                if let Some(role) = entry.role {
+                    output.push_str(&format!(" ({})", role.as_str()));
+                }
+                output.push_str(":\n");
+                if let Some(quote) = reply_quote(entries, entry) {
+                    output.push_str(&format!("    ↳ {}\n", quote));
+                }
+                for line in body_text(entry).lines() {
+                    output.push_str(&format!("    {}\n", line));
+                }
+                if let Some(attachment) = entry.attachment.as_ref() {
+                    output.push_str(&format!("    📎 {}\n", sanitize(&attachment.describe())));
+                }
+            }
+            output
+        }
+    }
+}
+
+pub async fn export_to_file(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    roles: &HashMap<String, Role>,
+    keyring: &Keyring,
+    format: Format,
+    options: &ExportOptions,
+    path: &std::path::Path,
+) -> Result<usize, String> {
+    let entries = read_transcript(con, chat_id, roles, keyring, options).await?;
+    std::fs::write(path, export(chat_id, &entries, format))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(entries.len())
+}
+
+pub fn import(jsonl: &str) -> Result<Vec<Entry>, String> {
+    jsonl
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+pub async fn replay(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    author: &str,
+    key: Option<&SigningKey>,
+    entries: &[Entry],
+    mode: ReplayMode,
+) -> Result<usize, (usize, String)> {
+    let limits = Limits::default();
+    let mut previous: Option<i64> = None;
+    let mut replayed_ids = HashMap::new();
+    for entry in entries {
+        let Some(body) = entry.body.as_deref() else {
+            continue;
+        };
+        if let (ReplayMode::Timed { speed }, Some(previous), Some(timestamp)) =
+            (mode, previous, entry.timestamp)
+        {
+            let delay = (timestamp - previous).max(0) as f64 / speed.max(f64::EPSILON);
+            tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+        }
+        previous = entry.timestamp.or(previous);
+        let attachment = entry
+            .attachment
+            .as_ref()
+            .and_then(|attachment| serde_json::to_string(attachment).ok());
+        let output = entry
+            .output
+            .as_ref()
+            .and_then(|output| serde_json::to_string(output).ok());
+        let reply_to = entry
+            .reply_to
+            .as_ref()
+            .and_then(|reply_to| replayed_ids.get(reply_to))
+            .cloned();
+        let mut items = vec![(REPLAY_FIELD, entry.author.as_str())];
+        if let Some(reply_to) = reply_to.as_deref() {
+            items.push((REPLY_FIELD, reply_to));
+        }
+        if let Some(attachment) = attachment.as_deref() {
+            items.push((ATTACHMENT_FIELD, attachment));
+        }
+        if let Some(output) = output.as_deref() {
+            items.push((OUTPUT_FIELD, output));
+        }
+        let id = loop {
+            match write_authored(
+                con,
+                chat_id,
+                key,
+                Some(&limits),
+                author,
+                body,
+                items.clone(),
+            )
+            .await
+            {
+                Ok(id) => break id,
+                Err(Rejection::RateLimited) => tokio::time::sleep(REPLAY_BACKOFF).await,
+                Err(rejection) => {
+                    return Err((
+                        replayed_ids.len(),
+                        format!("entry {}: {:?}", entry.id, rejection),
+                    ))
+                }
+            }
+        };
+        replayed_ids.insert(entry.id.clone(), id);
+    }
+    Ok(replayed_ids.len())
+}
+
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    value.parse::<i64>().or_else(|_| {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .map(|time| time.timestamp_millis())
+            .map_err(|e| format!("invalid time {:?}: {}", value, e))
+    })
+}
+
+fn reply_quote(entries: &[Entry], entry: &Entry) -> Option<String> {
+    let target = entry.reply_to.as_deref()?;
+    let quote = match entries.iter().find(|parent| parent.id == target) {
+        Some(parent) => format!(
+            "{}: {}",
+            sanitize(&parent.author),
+            body_text(parent).lines().next().unwrap_or_default()
+        ),
+        None => format!("<{}>", tr(Text::ReplyUnavailable)),
+    };
+    Some(quote)
+}
+
+fn body_text(entry: &Entry) -> String {
+    entry
+        .body
+        .as_deref()
+        .map(sanitize)
+        .unwrap_or_else(|| format!("<{}>", tr(Text::Undecodable)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        message::{
+            timestamp_millis, DELETE_FIELD, EDIT_FIELD, NOTE_FIELD, SIGNATURE_FIELD,
+            SIGNED_AT_FIELD,
+        },
+        signing::{ParticipantKey, SigningKey},
+    };
+
+    fn roles() -> HashMap<String, Role> {
+        HashMap::from([
+            ("Customer".to_owned(), Role::Customer),
+            ("Operator".to_owned(), Role::Operator),
+        ])
+    }
+
+    fn verified(
+        key: &SigningKey,
+        keyring: &Keyring,
+        id: &str,
+        body: &str,
+        controls: &[(&str, &str)],
+    ) -> Message {
+        let signed_at = timestamp_millis(id).unwrap();
+        let signature = key.sign("chat", "Customer", body, signed_at, controls);
+        let signed_at = signed_at.to_string();
+        let mut fields = controls.to_vec();
+        fields.extend([
+            ("Customer", body),
+            (SIGNED_AT_FIELD, &signed_at),
+            (SIGNATURE_FIELD, &signature),
+        ]);
+        let mut message = Message::from_fields(id, &fields).remove(0);
+        message.trust = keyring.verify("chat", &message);
+        message
+    }
+
+    fn unsigned(keyring: &Keyring, id: &str, fields: &[(&str, &str)]) -> Message {
+        let mut message = Message::from_fields(id, fields).remove(0);
+        message.trust = keyring.verify("chat", &message);
+        message
+    }
+
+    fn entry(author: &str, body: &str) -> Entry {
+        let message = Message::from_fields("1000-0", &[(author, body)]).remove(0);
+        Entry::from_message(message, &roles())
+    }
+
+    #[test]
+    fn redacts_known_fields_only() {
+        let mut entry = entry("Customer", "card 4111");
+        entry.redact("body").unwrap();
+        entry.redact("role").unwrap();
+        assert_eq!(entry.body.as_deref(), Some(REDACTED));
+        assert_eq!(entry.role, None);
+        assert!(entry.redact("password").is_err());
+    }
+
+    #[test]
+    fn parses_millis_and_rfc3339_times() {
+        assert_eq!(parse_time("1000"), Ok(1000));
+        assert_eq!(parse_time("1970-01-01T00:00:01Z"), Ok(1000));
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn exports_one_json_line_per_entry() {
+        let entries = [entry("Customer", "hello"), entry("Operator", "hi")];
+        let exported = export("chat", &entries, Format::JsonLines);
+        let lines: Vec<Entry> = exported
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, entries);
+    }
+
+    #[test]
+    fn text_exports_are_sanitized() {
+        let entries = [entry(
+            "Cust\u{1b}[31momer",
+            "line one\n\u{1b}]0;x\u{7}line two",
+        )];
+        let exported = export("chat", &entries, Format::PlainText);
+        assert!(!exported.contains('\u{1b}'));
+        assert!(exported.contains("Customer:\n    line one\n    line two\n"));
+        let exported = export("chat", &entries, Format::Markdown);
+        assert!(exported.contains("**Customer** —"));
+        assert!(exported.contains("> line one\n> line two\n"));
+    }
+
+    #[test]
+    fn imports_what_it_exports() {
+        let entries = [entry("Customer", "hello"), entry("Operator", "hi")];
+        let exported = export("chat", &entries, Format::JsonLines);
+        assert_eq!(import(&format!("{}\n\n", exported)).unwrap(), entries);
+    }
+
+    #[test]
+    fn import_reports_the_broken_line() {
+        let exported = export("chat", &[entry("Customer", "hello")], Format::JsonLines);
+        let error = import(&format!("{}{{\n", exported)).unwrap_err();
+        assert!(error.starts_with("line 2:"), "{}", error);
+    }
+
+    #[test]
+    fn edits_and_deletes_rewrite_the_transcript() {
+        let mut entries = vec![];
+        let messages = [
+            Message::from_fields("1000-0", &[("Customer", "hello")]),
+            Message::from_fields("1001-0", &[("Operator", "hi")]),
+            Message::from_fields("1002-0", &[(EDIT_FIELD, "1000-0"), ("Customer", "hello!")]),
+            Message::from_fields("1003-0", &[(EDIT_FIELD, "1001-0"), ("Customer", "forged")]),
+            Message::from_fields("1004-0", &[(DELETE_FIELD, "1001-0"), ("Operator", "")]),
+        ];
+        for message in messages.into_iter().flatten() {
+            apply(&mut entries, message, &roles());
+        }
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, "1000-0");
+        assert_eq!(entries[0].body.as_deref(), Some("hello!"));
+    }
+
+    #[test]
+    fn replies_quote_their_parent() {
+        let mut reply = entry("Operator", "hi");
+        reply.id = "1001-0".to_owned();
+        reply.reply_to = Some("1000-0".to_owned());
+        let exported = export(
+            "chat",
+            &[entry("Customer", "hello\nthere"), reply.clone()],
+            Format::PlainText,
+        );
+        assert!(exported.contains("Customer: hello\n"), "{}", exported);
+        reply.reply_to = Some("999-0".to_owned());
+        let exported = export("chat", &[reply], Format::PlainText);
+        assert!(exported.contains(&format!("<{}>", tr(Text::ReplyUnavailable))));
+    }
+
+    #[test]
+    fn unsigned_edits_and_deletes_do_not_rewrite_the_transcript() {
+        let key = SigningKey::generate();
+        let keys = HashMap::from([(
+            "Customer".to_owned(),
+            ParticipantKey {
+                public: key.public_hex(),
+                certificate: None,
+            },
+        )]);
+        let keyring = Keyring::from_keys("session", &keys, None);
+        let mut entries = vec![];
+        let messages = [
+            verified(&key, &keyring, "1000-0", "hello", &[]),
+            unsigned(
+                &keyring,
+                "1001-0",
+                &[(EDIT_FIELD, "1000-0"), ("Customer", "forged")],
+            ),
+            unsigned(
+                &keyring,
+                "1002-0",
+                &[(DELETE_FIELD, "1000-0"), ("Customer", "")],
+            ),
+            unsigned(&keyring, "1003-0", &[("Customer", "unsigned")]),
+        ];
+        for message in messages {
+            apply(&mut entries, message, &roles());
+        }
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].body.as_deref(), Some("hello"));
+        assert_eq!(entries[0].trust, Trust::Verified);
+        assert_eq!(entries[1].trust, Trust::Unsigned);
+        let edit = verified(
+            &key,
+            &keyring,
+            "1004-0",
+            "edited",
+            &[(EDIT_FIELD, "1000-0")],
+        );
+        apply(&mut entries, edit, &roles());
+        assert_eq!(entries[0].body.as_deref(), Some("edited"));
+        let delete = verified(&key, &keyring, "1005-0", "", &[(DELETE_FIELD, "1000-0")]);
+        apply(&mut entries, delete, &roles());
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn notes_are_not_exported() {
+        let roles = roles();
+        let mut entries = vec![];
+        let messages = Message::from_fields("1000-0", &[("Customer", "hello")])
+            .into_iter()
+            .chain(Message::from_fields(
+                "1001-0",
+                &[(NOTE_FIELD, "1"), ("Operator", "private")],
+            ));
+        for message in messages {
+            apply(&mut entries, message, &roles);
+        }
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].author, "Customer");
+        assert_eq!(entries[0].body.as_deref(), Some("hello"));
+        assert_eq!(entries[0].role, Some(Role::Customer));
+    }
+}
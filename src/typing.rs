@@ -0,0 +1,131 @@
+use crate::{connector::create_redis_client, controller_signals::ControllerSignal, role::Role};
+use futures_util::StreamExt;
+use redis::AsyncCommands;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+pub const TYPING_REFRESH: Duration = Duration::from_secs(2);
+pub const TYPING_EXPIRY: Duration = Duration::from_secs(5);
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TypingState {
+    pub name: String,
+    pub role: Role,
+    pub typing: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct TypingDebouncer {
+    last_sent: Option<Instant>,
+}
+
+impl TypingDebouncer {
+    pub fn input(&mut self) -> bool {
+        let now = Instant::now();
+        match self.last_sent {
+            Some(last_sent) if now.duration_since(last_sent) < TYPING_REFRESH => false,
+            _ => {
+                self.last_sent = Some(now);
+                true
+            }
+        }
+    }
+
+    pub fn stop(&mut self) -> bool {
+        self.last_sent.take().is_some()
+    }
+}
+
+pub async fn publish(
+    con: &mut redis::aio::MultiplexedConnection,
+    chat_id: &str,
+    name: &str,
+    role: Role,
+    typing: bool,
+) {
+    let state = TypingState {
+        name: name.to_owned(),
+        role,
+        typing,
+    };
+    let payload = serde_json::to_string(&state).unwrap_or_default();
+    let _: redis::RedisResult<()> = con.publish(typing_channel(chat_id), payload).await;
+}
+
+pub async fn keep_typing(chat_id: String, name: String, role: Role) {
+    let Ok(mut con) = crate::connector::try_create_async_redis_connection().await else {
+        return;
+    };
+    let mut interval = tokio::time::interval(TYPING_REFRESH);
+    loop {
+        interval.tick().await;
+        publish(&mut con, &chat_id, &name, role, true).await;
+    }
+}
+
+pub async fn typing_connector(chat_id: String, name: String, tx: mpsc::Sender<ControllerSignal>) {
+    loop {
+        if let Err(e) = listen(&chat_id, &name, &tx).await {
+            eprintln!("Typing subscription failed: {:?}", e);
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn listen(
+    chat_id: &str,
+    name: &str,
+    tx: &mpsc::Sender<ControllerSignal>,
+) -> redis::RedisResult<()> {
+    let mut pubsub = create_redis_client()?.get_async_pubsub().await?;
+    pubsub.subscribe(typing_channel(chat_id)).await?;
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let Ok(payload) = message.get_payload::<String>() else {
+            continue;
+        };
+        match serde_json::from_str::<TypingState>(&payload) {
+            Ok(state) if state.name != name => {
+                let _ = tx.send(ControllerSignal::Typing { state }).await;
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Bad typing payload {:?}: {:?}", payload, e),
+        }
+    }
+    Ok(())
+}
+
+fn typing_channel(chat_id: &str) -> String {
+    format!("typing:{}", chat_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debouncer_sends_once_per_refresh() {
+        let mut debouncer = TypingDebouncer::default();
+        assert!(!debouncer.stop());
+        assert!(debouncer.input());
+        assert!(!debouncer.input());
+        assert!(debouncer.stop());
+        assert!(debouncer.input());
+    }
+
+    #[test]
+    fn typing_state_round_trips_as_json() {
+        let state = TypingState {
+            name: "Operator".to_owned(),
+            role: Role::Operator,
+            typing: true,
+        };
+        let payload = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            serde_json::from_str::<TypingState>(&payload).unwrap(),
+            state
+        );
+    }
+}
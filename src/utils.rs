@@ -1,6 +1,6 @@
 use redis::JsonCommands;
 
-use crate::{connector::write_to_stream, session::Session};
+use crate::{e2e::ChatKey, output::send_user_output, session::Session, signing::SigningKey};
 
 pub fn extract_one_string_from_array(v: &serde_json::Value) -> Option<String> {
     v.as_array()
@@ -30,22 +30,31 @@ pub fn blocking_update_session_timestamp(con: &mut redis::Connection, session_id
 pub async fn user_output_into_session(
     con: &mut redis::aio::MultiplexedConnection,
     session: &Session,
+    key: Option<&SigningKey>,
+    chat_key: Option<&ChatKey>,
     user_output: serde_json::Value,
 ) {
-    let output = match user_output {
-        serde_json::Value::Array(a) => a,
-        serde_json::Value::Object(o) => o.values().cloned().collect(),
-        v => vec![v],
-    };
-    for msg in output
-        .into_iter()
-        .filter_map(|v| v.as_str().map(ToOwned::to_owned))
-    {
-        write_to_stream(
-            con,
-            &session.chat_id,
-            &[(session.robot.as_str(), msg.as_str())],
-        )
-        .await;
+    send_user_output(
+        con,
+        &session.chat_id,
+        &session.robot,
+        key,
+        chat_key,
+        user_output,
+    )
+    .await;
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
     }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
 }